@@ -1,31 +1,58 @@
-/// Creates a buffer with an initial capacity and optional limit
-#[derive(Debug, PartialEq)]
-pub(crate) struct Buffer {
+use std::collections::VecDeque;
+
+use super::MarkBufferError;
+
+/// Creates a buffer with an initial capacity and optional limit.
+/// Bytes are held in a ring buffer so that reclaiming space from
+/// bytes that have been read does not require shifting the remaining bytes.
+///
+/// The buffer holds bytes by default, but can hold any `Copy` value, such as
+/// decoded tokens, in which case the limit and lengths are counted in values.
+#[derive(Clone, Debug, PartialEq)]
+pub(crate) struct Buffer<T = u8> {
     pos: usize,
     size: usize,
     buffer_limit: Option<usize>,
-    buffer: Vec<u8>,
+    initial_capacity: usize,
+    buffer: VecDeque<T>,
 }
 
-impl Buffer {
+impl<T> Buffer<T>
+where
+    T: Copy,
+{
     /// Creates a new buffer with the provided initial capacity and optional limit.
-    pub fn new(buffer_size: usize, buffer_limit: Option<usize>) -> Buffer {
+    pub fn new(buffer_size: usize, buffer_limit: Option<usize>) -> Buffer<T> {
         Buffer {
             pos: 0,
             size: 0,
             buffer_limit,
-            buffer: Vec::with_capacity(buffer_size),
+            initial_capacity: buffer_size,
+            buffer: VecDeque::with_capacity(buffer_size),
         }
     }
 
-    /// Clears the buffer and returns how many bytes were dropped
+    /// Clears the buffer and returns how many unread bytes were dropped
     pub fn clear(&mut self) -> usize {
-        let dropped = self.buffer.len() - self.pos;
+        debug_assert!(
+            self.pos <= self.buffer.len(),
+            "position should never pass the end of the buffer"
+        );
+        let dropped = self.buffer.len().saturating_sub(self.pos);
         self.pos = 0;
         self.buffer.clear();
         dropped
     }
 
+    /// Drops up to `n` of the oldest read values from the front of the buffer, leaving
+    /// unread values in place. Returns the number of values dropped
+    pub fn discard_oldest(&mut self, n: usize) -> usize {
+        let dropped = n.min(self.pos);
+        self.buffer.drain(0..dropped);
+        self.pos -= dropped;
+        dropped
+    }
+
     pub fn purge_read(&mut self) -> usize {
         let dropped = self.pos;
         self.buffer.drain(0..self.pos);
@@ -33,31 +60,188 @@ impl Buffer {
         dropped
     }
 
-    pub fn restart(&mut self) {
+    /// Removes and returns the bytes that have already been read, leaving the
+    /// unread bytes in place
+    pub fn take_read(&mut self) -> Vec<T> {
+        let read = self.buffer.drain(0..self.pos).collect();
         self.pos = 0;
+        read
+    }
+
+    /// Returns the position to the start of the buffer so previously read bytes
+    /// can be read again. Returns the number of bytes that will be re-read.
+    pub fn restart(&mut self) -> usize {
+        let rewound = self.pos;
+        self.pos = 0;
+        rewound
+    }
+
+    /// Gets the number of bytes that have been read since the start of the buffer
+    pub fn consumed(&self) -> usize {
+        self.pos
+    }
+
+    /// Moves the position back to the provided offset so the bytes after it can be
+    /// read again. Returns the number of bytes that will be re-read.
+    pub fn rewind_to(&mut self, offset: usize) -> usize {
+        let rewound = self.pos.saturating_sub(offset);
+        self.pos -= rewound;
+        rewound
+    }
+
+    /// Returns the unread bytes in the buffer without advancing the position.
+    /// This may rearrange the underlying ring buffer to make the bytes contiguous.
+    pub fn as_slice(&mut self) -> &[T] {
+        let pos = self.pos;
+        &self.buffer.make_contiguous()[pos..]
+    }
+
+    /// Returns up to `n` unread values from the start of the unread region, stopping
+    /// early where the ring buffer wraps around. Unlike `as_slice`, this never
+    /// rearranges the buffer
+    pub fn unread_front(&self, n: usize) -> &[T] {
+        let (front, back) = self.buffer.as_slices();
+        let unread = if self.pos < front.len() {
+            &front[self.pos..]
+        } else {
+            &back[self.pos - front.len()..]
+        };
+        &unread[..n.min(unread.len())]
+    }
+
+    /// Returns a copy of the unread bytes in the buffer without advancing the position
+    pub fn to_vec(&self) -> Vec<T> {
+        self.buffer.range(self.pos..).copied().collect()
+    }
+
+    /// Removes and returns the unread bytes, leaving previously read bytes in place
+    pub fn split_unread(&mut self) -> Vec<T> {
+        self.buffer.split_off(self.pos).into()
+    }
+
+    /// Inserts a slice in front of the unread bytes so it will be read next.
+    /// If a buffer limit has been imposed and this will
+    /// exceed that limit, an out of memory error will be returned.
+    pub fn prepend(&mut self, buf: &[T]) -> std::io::Result<()> {
+        if self.size_exceeds_capacity(buf.len()) {
+            return Err(self.limit_exceeded(self.len() + buf.len()));
+        }
+
+        if self.pos == 0 {
+            for byte in buf.iter().rev() {
+                self.buffer.push_front(*byte);
+            }
+        } else {
+            let unread = self.buffer.split_off(self.pos);
+            self.buffer.extend(buf);
+            self.buffer.extend(unread);
+        }
+        Ok(())
+    }
+
+    /// Appends a slice that has already been read by the caller. Unlike `append`,
+    /// previously read bytes are retained so they can be replayed after a `restart`.
+    /// If a buffer limit has been imposed and retaining these bytes will
+    /// exceed that limit, an out of memory error will be returned.
+    pub fn record(&mut self, buf: &[T]) -> std::io::Result<()> {
+        let exceeds_limit = self
+            .buffer_limit
+            .map(|limit| (self.buffer.len() + buf.len()) > limit)
+            .unwrap_or(false);
+        if exceeds_limit {
+            return Err(self.limit_exceeded(self.buffer.len() + buf.len()));
+        }
+
+        self.buffer.extend(buf);
+        self.pos = self.buffer.len();
+        Ok(())
+    }
+
+    /// Replaces the limit of the buffer. If the buffer already holds more bytes than
+    /// the new limit, including those already read, an invalid input error is
+    /// returned and the limit is left unchanged.
+    pub fn set_limit(&mut self, buffer_limit: Option<usize>) -> std::io::Result<()> {
+        if buffer_limit.is_some_and(|limit| self.buffer.len() > limit) {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "buffer already holds more bytes than the requested limit",
+            ));
+        }
+
+        self.buffer_limit = buffer_limit;
+        Ok(())
+    }
+
+    /// Ensures at least `additional` bytes can be appended without resizing
+    /// the underlying buffer, discarding previously read bytes if necessary
+    pub fn reserve(&mut self, additional: usize) {
+        if self.get_available_space() < additional {
+            self.purge_read();
+            self.buffer.reserve(additional);
+        }
+    }
+
+    /// Ensures at least `additional` more values can be stored without reallocating,
+    /// keeping values that have already been read. The reservation is capped so the
+    /// buffer never grows beyond its limit, if set.
+    pub fn reserve_retained(&mut self, additional: usize) {
+        let additional = self
+            .buffer_limit
+            .map(|limit| additional.min(limit.saturating_sub(self.buffer.len())))
+            .unwrap_or(additional);
+        self.buffer.reserve_exact(additional);
     }
 
     /// Reads values from this buffer into the provided `buf`.
     /// Returns the number of bytes placed in the provided `buf`
-    pub fn read_into(&mut self, buf: &mut [u8], offset: usize) -> usize {
+    pub fn read_into(&mut self, buf: &mut [T], offset: usize) -> usize {
         let requested_byte_count = buf.len() - offset.min(buf.len());
         let internal_buffer_remaining = self.buffer.len() - self.pos.min(self.buffer.len());
         let bytes_to_read = internal_buffer_remaining.min(requested_byte_count);
 
-        for i in 0..bytes_to_read {
-            buf[i + offset] = self.buffer[i + self.pos];
+        // The ring buffer holds its bytes in up to two slices, so copy
+        // whatever part of the requested range falls within each
+        let (front, back) = self.buffer.as_slices();
+        let start = self.pos;
+        let end = self.pos + bytes_to_read;
+        let target = &mut buf[offset..offset + bytes_to_read];
+        if end <= front.len() {
+            target.copy_from_slice(&front[start..end]);
+        } else if start >= front.len() {
+            target.copy_from_slice(&back[start - front.len()..end - front.len()]);
+        } else {
+            let (front_target, back_target) = target.split_at_mut(front.len() - start);
+            front_target.copy_from_slice(&front[start..]);
+            back_target.copy_from_slice(&back[0..end - front.len()]);
         }
 
         self.pos += bytes_to_read;
         bytes_to_read
     }
 
+    /// Marks up to `n` unread values as read without copying them out.
+    /// Returns the number of values skipped
+    pub fn skip(&mut self, n: usize) -> usize {
+        let skipped = n.min(self.len());
+        self.pos += skipped;
+        skipped
+    }
+
+    /// Appends all of the unread bytes to the provided `Vec`, marking them as read.
+    /// Returns the number of bytes appended
+    pub fn read_to_vec(&mut self, buf: &mut Vec<T>) -> usize {
+        let bytes_read = self.len();
+        buf.extend(self.buffer.range(self.pos..));
+        self.pos = self.buffer.len();
+        bytes_read
+    }
+
     /// Appends a slice into the buffer.
     /// If a buffer limit has been imposed and this will
     /// exceed that limit, an out of memory error will be returned.
-    fn append(&mut self, buf: &[u8]) -> std::io::Result<()> {
+    pub fn append(&mut self, buf: &[T]) -> std::io::Result<()> {
         if self.size_exceeds_capacity(buf.len()) {
-            return Err(std::io::Error::from(std::io::ErrorKind::OutOfMemory));
+            return Err(self.limit_exceeded(self.len() + buf.len()));
         }
 
         self.prepare_for_bytes(buf.len());
@@ -65,6 +249,15 @@ impl Buffer {
         Ok(())
     }
 
+    /// Builds the error returned when holding `used` values would exceed the limit
+    fn limit_exceeded(&self, used: usize) -> std::io::Error {
+        MarkBufferError::LimitExceeded {
+            used,
+            limit: self.buffer_limit.unwrap_or(usize::MAX),
+        }
+        .into()
+    }
+
     /// Determines if a byte size will exceed the limit, if set, of this buffer
     fn size_exceeds_capacity(&self, size: usize) -> bool {
         let used_space = self.len();
@@ -79,6 +272,37 @@ impl Buffer {
         self.buffer.len() - self.pos
     }
 
+    /// Gets the length of all bytes in the buffer, including those already read
+    pub fn stored_len(&self) -> usize {
+        self.buffer.len()
+    }
+
+    /// Gets the number of bytes the buffer can hold without reallocating
+    pub fn capacity(&self) -> usize {
+        self.buffer.capacity()
+    }
+
+    /// Releases any capacity beyond what is needed for the stored bytes
+    pub fn shrink_to_fit(&mut self) {
+        self.buffer.shrink_to_fit();
+    }
+
+    /// Gets the capacity the buffer was created with
+    pub fn initial_capacity(&self) -> usize {
+        self.initial_capacity
+    }
+
+    /// Releases any capacity beyond the capacity the buffer was created with, keeping
+    /// enough for the stored bytes
+    pub fn shrink_to_initial(&mut self) {
+        self.buffer.shrink_to(self.initial_capacity);
+    }
+
+    /// Gets the limit of the buffer, if any
+    pub fn limit(&self) -> Option<usize> {
+        self.buffer_limit
+    }
+
     /// Gets the available space within the buffer that is available without
     /// resizing the underlying buffer
     pub fn get_available_space(&self) -> usize {
@@ -87,27 +311,26 @@ impl Buffer {
 
     /// Prepares the internal buffer to receive data of the provided size
     /// If the provided size is larger than the available space, previously
-    /// read elements are removed and vec is shifted left for the new elements
-    /// to be appended
+    /// read elements are removed from the front of the ring buffer,
+    /// which does not move the remaining elements
     fn prepare_for_bytes(&mut self, byte_size: usize) {
-        // Available space is the data the buffer can hold, less current amount of
-        // data in the buffer, plus the current position which represents read
-        // (i.e., now available) space
-        let available_space = self.get_available_space();
-        if byte_size > available_space {
-            let _ = self.buffer.drain(0..self.pos);
-            self.pos = 0; // Reset the position
+        // Read bytes are only reclaimed once the spare capacity of the underlying
+        // buffer runs out, as appending past it would otherwise reallocate even
+        // though the read (i.e., now available) space could hold the data
+        let spare_capacity = self.buffer.capacity() - self.buffer.len();
+        if byte_size > spare_capacity {
+            self.purge_read();
         }
     }
 }
 
-impl std::io::Read for Buffer {
+impl std::io::Read for Buffer<u8> {
     fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
         Ok(self.read_into(buf, 0))
     }
 }
 
-impl std::io::Write for Buffer {
+impl std::io::Write for Buffer<u8> {
     fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
         self.append(buf)?;
         Ok(buf.len())
@@ -168,7 +391,7 @@ mod tests {
         buffer.write_all(&values).unwrap();
 
         buffer
-            .read(&mut values)
+            .read_exact(&mut values)
             .expect("should be able to read value");
         values = vec![0, 1];
         buffer
@@ -214,4 +437,208 @@ mod tests {
             "values and read buffer should be identical"
         );
     }
+
+    #[test]
+    fn test_repeated_purge_of_large_buffer() {
+        let region_size = 1024 * 1024;
+        let chunk_size = 256;
+        let values: Vec<u8> = (0..region_size).map(|i| (i % 251) as u8).collect();
+        let mut buffer = Buffer::new(2, None);
+        buffer
+            .record(&values)
+            .expect("should be able to record 1MB");
+        buffer.restart();
+
+        let mut chunk = vec![0; chunk_size];
+        for (i, expected) in values.chunks(chunk_size).enumerate() {
+            buffer.purge_read();
+            assert_eq!(
+                region_size - i * chunk_size,
+                buffer.stored_len(),
+                "purging should drop only the bytes already read"
+            );
+            assert_eq!(
+                chunk_size,
+                buffer.read_into(&mut chunk, 0),
+                "should be able to read a chunk"
+            );
+            assert_eq!(
+                expected,
+                &chunk[..],
+                "purging should not disturb the unread bytes"
+            );
+        }
+
+        assert_eq!(0, buffer.len(), "all bytes should have been read");
+    }
+
+    #[test]
+    fn test_large_read_into() {
+        let size = 64 * 1024;
+        let values: Vec<u8> = (0..size).map(|i| (i % 251) as u8).collect();
+        let mut buffer = Buffer::new(size, None);
+        buffer.write_all(&values[0..size / 2]).unwrap();
+
+        // Read part of the buffer and refill so that the bytes wrap around the ring
+        let mut read_buf = vec![0; size];
+        assert_eq!(size / 4, buffer.read_into(&mut read_buf[0..size / 4], 0));
+        buffer.write_all(&values[size / 2..]).unwrap();
+        buffer.purge_read();
+        buffer.write_all(&values[0..size / 4]).unwrap();
+
+        assert_eq!(
+            size,
+            buffer.read_into(&mut read_buf, 0),
+            "should be able to read the whole buffer"
+        );
+        assert_eq!(values[size / 4..], read_buf[0..size * 3 / 4]);
+        assert_eq!(values[0..size / 4], read_buf[size * 3 / 4..]);
+    }
+
+    #[test]
+    fn test_read_into_with_offset() {
+        let mut buffer = Buffer::new(4, None);
+        buffer.write_all(&[1, 2, 3]).unwrap();
+
+        let mut read_buf = vec![0; 4];
+        assert_eq!(3, buffer.read_into(&mut read_buf, 1), "should read 3 bytes");
+        assert_eq!(vec![0, 1, 2, 3], read_buf, "bytes should follow the offset");
+    }
+
+    #[test]
+    fn test_typed_buffer() {
+        let mut buffer: Buffer<u32> = Buffer::new(4, Some(4));
+        buffer
+            .append(&[100, 200, 300])
+            .expect("should be able to append 3 values");
+        assert_eq!(3, buffer.len(), "length should be counted in values");
+
+        let mut values = [0; 2];
+        assert_eq!(2, buffer.read_into(&mut values, 0), "should read 2 values");
+        assert_eq!([100, 200], values, "values should be read in order");
+
+        buffer
+            .append(&[400, 500, 600])
+            .expect("read values should be reclaimed for appends");
+        assert_eq!(
+            std::io::ErrorKind::OutOfMemory,
+            buffer.append(&[700]).unwrap_err().kind(),
+            "limit should be counted in values"
+        );
+
+        let mut values = [0; 5];
+        assert_eq!(
+            4,
+            buffer.read_into(&mut values, 1),
+            "should read the remaining values"
+        );
+        assert_eq!(
+            [0, 300, 400, 500, 600],
+            values,
+            "values should be read after the offset"
+        );
+    }
+
+    #[test]
+    fn test_typed_buffer_replay() {
+        let mut buffer: Buffer<char> = Buffer::new(0, None);
+        buffer
+            .record(&['a', 'b'])
+            .expect("should be able to record 2 values");
+        assert_eq!(2, buffer.restart(), "recorded values should be replayed");
+
+        let mut values = ['-'; 2];
+        assert_eq!(2, buffer.read_into(&mut values, 0), "should read 2 values");
+        assert_eq!(['a', 'b'], values, "replayed values should match");
+    }
+
+    #[test]
+    fn test_clear_without_unread_bytes() {
+        let mut buffer = Buffer::new(4, None);
+        assert_eq!(0, buffer.clear(), "empty buffer should drop nothing");
+
+        buffer
+            .write_all(&[1, 2, 3])
+            .expect("should be able to write 3 bytes");
+        let mut values = vec![0; 3];
+        buffer
+            .read_exact(&mut values)
+            .expect("should be able to read 3 bytes");
+        assert_eq!(0, buffer.clear(), "fully read buffer should drop nothing");
+        assert_eq!(0, buffer.stored_len(), "read bytes should still be cleared");
+    }
+
+    #[test]
+    fn test_discard_oldest() {
+        let mut buffer = Buffer::new(8, None);
+        buffer.record(&[0, 1, 2, 3]).unwrap();
+        buffer.restart();
+        let mut byte = [0; 3];
+        buffer.read_exact(&mut byte).unwrap();
+
+        assert_eq!(
+            2,
+            buffer.discard_oldest(2),
+            "should drop the oldest read bytes"
+        );
+        assert_eq!(2, buffer.stored_len(), "should keep the newer bytes");
+        assert_eq!(1, buffer.discard_oldest(5), "should only drop read bytes");
+        assert_eq!(
+            vec![3],
+            buffer.to_vec(),
+            "unread bytes should never be dropped"
+        );
+    }
+
+    #[test]
+    fn test_unread_front() {
+        let mut buffer = Buffer::new(4, None);
+        buffer.append(&[0, 1, 2, 3]).unwrap();
+        let mut byte = [0; 3];
+        buffer.read_exact(&mut byte).unwrap();
+        buffer.append(&[4, 5]).unwrap();
+
+        assert_eq!(
+            &[3],
+            buffer.unread_front(3),
+            "should stop where the ring wraps"
+        );
+        buffer.read_exact(&mut byte[..1]).unwrap();
+        assert_eq!(
+            &[4, 5],
+            buffer.unread_front(3),
+            "should continue after the wrap"
+        );
+        assert_eq!(&[4], buffer.unread_front(1), "should be capped at n");
+    }
+
+    #[test]
+    fn test_skip() {
+        let mut buffer = Buffer::new(4, None);
+        buffer.append(&[0, 1, 2]).unwrap();
+        assert_eq!(2, buffer.skip(2), "should skip the requested values");
+        assert_eq!(1, buffer.skip(5), "should only skip unread values");
+        assert_eq!(0, buffer.len(), "all values should be read");
+        assert_eq!(3, buffer.stored_len(), "skipped values should stay stored");
+    }
+
+    #[test]
+    fn test_shrink_to_initial() {
+        let mut buffer = Buffer::new(16, None);
+        buffer.record(&[1; 1024]).unwrap();
+        assert!(buffer.capacity() >= 1024, "buffer should grow");
+        buffer.shrink_to_initial();
+        assert!(buffer.capacity() >= 1024, "stored bytes should be kept");
+
+        buffer.clear();
+        buffer.shrink_to_initial();
+        assert!(
+            buffer.capacity() < 1024,
+            "capacity should drop back towards the initial capacity"
+        );
+        assert!(
+            buffer.capacity() >= 16,
+            "the initial capacity should be kept"
+        );
+    }
 }
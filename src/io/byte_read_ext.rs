@@ -0,0 +1,153 @@
+use std::io::Read;
+
+/// Generates a method reading a fixed-width integer using the given conversion
+macro_rules! read_int {
+    ($(#[$doc:meta])* $name:ident, $int:ty, $from_bytes:ident) => {
+        $(#[$doc])*
+        fn $name(&mut self) -> std::io::Result<$int> {
+            let mut bytes = [0; std::mem::size_of::<$int>()];
+            self.read_exact(&mut bytes)?;
+            Ok(<$int>::$from_bytes(bytes))
+        }
+    };
+}
+
+/// Decodes fixed-width integers from any reader. Each method reads exactly the
+/// bytes it needs, returning an `ErrorKind::UnexpectedEof` error if the stream ends
+/// first. Because these build on `read_exact`, a failed decode on a marked stream
+/// can be undone with `reset`.
+pub trait ByteReadExt: Read {
+    read_int!(
+        /// Reads an unsigned byte
+        read_u8, u8, from_le_bytes
+    );
+    read_int!(
+        /// Reads a signed byte
+        read_i8, i8, from_le_bytes
+    );
+    read_int!(
+        /// Reads a little endian `u16`
+        read_u16_le, u16, from_le_bytes
+    );
+    read_int!(
+        /// Reads a big endian `u16`
+        read_u16_be, u16, from_be_bytes
+    );
+    read_int!(
+        /// Reads a little endian `i16`
+        read_i16_le, i16, from_le_bytes
+    );
+    read_int!(
+        /// Reads a big endian `i16`
+        read_i16_be, i16, from_be_bytes
+    );
+    read_int!(
+        /// Reads a little endian `u32`
+        read_u32_le, u32, from_le_bytes
+    );
+    read_int!(
+        /// Reads a big endian `u32`
+        read_u32_be, u32, from_be_bytes
+    );
+    read_int!(
+        /// Reads a little endian `i32`
+        read_i32_le, i32, from_le_bytes
+    );
+    read_int!(
+        /// Reads a big endian `i32`
+        read_i32_be, i32, from_be_bytes
+    );
+    read_int!(
+        /// Reads a little endian `u64`
+        read_u64_le, u64, from_le_bytes
+    );
+    read_int!(
+        /// Reads a big endian `u64`
+        read_u64_be, u64, from_be_bytes
+    );
+    read_int!(
+        /// Reads a little endian `i64`
+        read_i64_le, i64, from_le_bytes
+    );
+    read_int!(
+        /// Reads a big endian `i64`
+        read_i64_be, i64, from_be_bytes
+    );
+}
+
+impl<R> ByteReadExt for R where R: Read + ?Sized {}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use crate::io::MarkerStream;
+    use crate::{BufferedMarkableReader, MarkableReader};
+
+    use super::ByteReadExt;
+
+    #[test]
+    fn test_read_little_endian() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![
+            0xFF, 0x34, 0x12, 0x78, 0x56, 0x34, 0x12, 0xFE, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+            0xFF,
+        ]));
+        assert_eq!(-1, reader.read_i8().expect("should be able to read an i8"));
+        assert_eq!(
+            0x1234,
+            reader.read_u16_le().expect("should be able to read a u16")
+        );
+        assert_eq!(
+            0x12345678,
+            reader.read_u32_le().expect("should be able to read a u32")
+        );
+        assert_eq!(
+            -2,
+            reader.read_i64_le().expect("should be able to read an i64")
+        );
+    }
+
+    #[test]
+    fn test_read_big_endian() {
+        let mut reader = BufferedMarkableReader::new(Cursor::new(vec![
+            0x07, 0x12, 0x34, 0xFF, 0xFE, 0x12, 0x34, 0x56, 0x78, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x01, 0x00,
+        ]));
+        assert_eq!(7, reader.read_u8().expect("should be able to read a u8"));
+        assert_eq!(
+            0x1234,
+            reader.read_u16_be().expect("should be able to read a u16")
+        );
+        assert_eq!(
+            -2,
+            reader.read_i16_be().expect("should be able to read an i16")
+        );
+        assert_eq!(
+            0x12345678,
+            reader.read_u32_be().expect("should be able to read a u32")
+        );
+        assert_eq!(
+            0x100,
+            reader.read_u64_be().expect("should be able to read a u64")
+        );
+    }
+
+    #[test]
+    fn test_failed_read_is_resettable() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![0x34, 0x12]));
+        reader.mark();
+        assert_eq!(
+            std::io::ErrorKind::UnexpectedEof,
+            reader.read_u32_le().unwrap_err().kind(),
+            "should not be able to read a u32 from 2 bytes"
+        );
+
+        reader.reset();
+        assert_eq!(
+            0x1234,
+            reader
+                .read_u16_le()
+                .expect("should be able to read a u16 after reset")
+        );
+    }
+}
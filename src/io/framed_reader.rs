@@ -0,0 +1,219 @@
+use std::io::Read;
+
+use super::BufferedMarkableReader;
+
+/// Reads length-prefixed frames, where each frame is a big endian length of a
+/// configurable width followed by that many payload bytes.
+///
+/// Each frame is read within a transaction, so if the inner reader fails partway
+/// through a frame (e.g., with `ErrorKind::WouldBlock` on a non-blocking source)
+/// the stream is reset to the start of the frame and the read can be retried
+/// later without losing the length prefix.
+pub struct FramedReader<R> {
+    reader: BufferedMarkableReader<R>,
+    prefix_width: usize,
+}
+
+impl<R> FramedReader<R>
+where
+    R: Read,
+{
+    /// Creates a new reader for frames with a length prefix of `prefix_width` bytes.
+    ///
+    /// # Panics
+    /// Panics if `prefix_width` is not between 1 and 8.
+    pub fn new(inner: R, prefix_width: usize) -> FramedReader<R> {
+        FramedReader::from_reader(BufferedMarkableReader::new(inner), prefix_width)
+    }
+
+    /// Creates a new reader for frames with a length prefix of `prefix_width` bytes,
+    /// using an existing reader, which allows its buffer sizes and limits to be chosen.
+    ///
+    /// # Panics
+    /// Panics if `prefix_width` is not between 1 and 8.
+    pub fn from_reader(reader: BufferedMarkableReader<R>, prefix_width: usize) -> FramedReader<R> {
+        assert!(
+            (1..=8).contains(&prefix_width),
+            "prefix width must be between 1 and 8 bytes"
+        );
+        FramedReader {
+            reader,
+            prefix_width,
+        }
+    }
+
+    /// Reads the payload of the next frame, or returns `None` if the stream ends
+    /// cleanly before another frame starts.
+    ///
+    /// Returns an `ErrorKind::UnexpectedEof` error if the stream ends partway through
+    /// a frame. On any error the stream is reset to the start of the frame.
+    pub fn read_frame(&mut self) -> std::io::Result<Option<Vec<u8>>> {
+        if self.reader.peek(1)?.is_empty() {
+            return Ok(None);
+        }
+
+        let prefix_width = self.prefix_width;
+        self.reader.transaction(|reader| {
+            let mut prefix = [0; 8];
+            reader.read_exact(&mut prefix[8 - prefix_width..])?;
+            let length = u64::from_be_bytes(prefix);
+
+            let mut payload = Vec::new();
+            reader.by_ref().take(length).read_to_end(&mut payload)?;
+            if (payload.len() as u64) < length {
+                return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof));
+            }
+
+            Ok(Some(payload))
+        })
+    }
+
+    /// Gets a reference to the underlying markable reader
+    pub fn get_ref(&self) -> &BufferedMarkableReader<R> {
+        &self.reader
+    }
+
+    /// Gets a mutable reference to the underlying markable reader
+    pub fn get_mut(&mut self) -> &mut BufferedMarkableReader<R> {
+        &mut self.reader
+    }
+
+    /// Returns the underlying markable reader, including any frames it has buffered
+    pub fn into_inner(self) -> BufferedMarkableReader<R> {
+        self.reader
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        cell::RefCell,
+        collections::VecDeque,
+        io::{Cursor, Read},
+        rc::Rc,
+    };
+
+    use crate::BufferedMarkableReader;
+
+    use super::FramedReader;
+
+    fn frame(prefix_width: usize, payload: &[u8]) -> Vec<u8> {
+        let prefix = (payload.len() as u64).to_be_bytes();
+        let mut frame = prefix[8 - prefix_width..].to_vec();
+        frame.extend_from_slice(payload);
+        frame
+    }
+
+    #[test]
+    fn test_multiple_frames() {
+        let mut input_data = frame(2, b"hello");
+        input_data.extend(frame(2, b""));
+        input_data.extend(frame(2, b"world!"));
+        let mut reader = FramedReader::new(Cursor::new(input_data), 2);
+
+        assert_eq!(
+            Some(b"hello".to_vec()),
+            reader.read_frame().expect("first frame")
+        );
+        assert_eq!(Some(Vec::new()), reader.read_frame().expect("empty frame"));
+        assert_eq!(
+            Some(b"world!".to_vec()),
+            reader.read_frame().expect("last frame")
+        );
+        assert_eq!(
+            None,
+            reader.read_frame().expect("clean eof"),
+            "no more frames"
+        );
+    }
+
+    #[test]
+    fn test_frame_across_buffer_boundaries() {
+        let payload: Vec<u8> = (0..100).collect();
+        let mut input_data = frame(4, &payload[..10]);
+        input_data.extend(frame(4, &payload));
+        let inner =
+            BufferedMarkableReader::new_with_capacity_and_limit(Cursor::new(input_data), 128, 16);
+        let mut reader = FramedReader::from_reader(inner, 4);
+
+        assert_eq!(
+            Some(payload[..10].to_vec()),
+            reader.read_frame().expect("first frame")
+        );
+        assert_eq!(Some(payload), reader.read_frame().expect("spanning frame"));
+        assert_eq!(
+            None,
+            reader.read_frame().expect("clean eof"),
+            "no more frames"
+        );
+    }
+
+    #[test]
+    fn test_truncated_trailing_frame() {
+        let mut input_data = frame(1, b"ok");
+        input_data.extend(&frame(1, b"truncated")[..4]);
+        let mut reader = FramedReader::new(Cursor::new(input_data.clone()), 1);
+
+        assert_eq!(
+            Some(b"ok".to_vec()),
+            reader.read_frame().expect("first frame")
+        );
+        assert_eq!(
+            std::io::ErrorKind::UnexpectedEof,
+            reader.read_frame().unwrap_err().kind(),
+            "truncated frame should not be returned"
+        );
+
+        let mut rest = Vec::new();
+        reader
+            .get_mut()
+            .read_to_end(&mut rest)
+            .expect("should be able to read the truncated frame");
+        assert_eq!(
+            &input_data[3..],
+            rest,
+            "truncated frame should not be consumed"
+        );
+    }
+
+    /// Returns `WouldBlock` whenever there are no bytes available, like a non-blocking socket
+    struct NonBlockingReader {
+        available: Rc<RefCell<VecDeque<u8>>>,
+    }
+
+    impl Read for NonBlockingReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let mut available = self.available.borrow_mut();
+            if available.is_empty() {
+                return Err(std::io::Error::from(std::io::ErrorKind::WouldBlock));
+            }
+
+            available.read(buf)
+        }
+    }
+
+    #[test]
+    fn test_partial_frame_is_retried() {
+        let input_data = frame(2, b"partial");
+        let available = Rc::new(RefCell::new(VecDeque::from(input_data[..5].to_vec())));
+        let mut reader = FramedReader::new(
+            NonBlockingReader {
+                available: available.clone(),
+            },
+            2,
+        );
+
+        assert_eq!(
+            std::io::ErrorKind::WouldBlock,
+            reader.read_frame().unwrap_err().kind(),
+            "partial frame should not be returned"
+        );
+
+        available.borrow_mut().extend(&input_data[5..]);
+        assert_eq!(
+            Some(b"partial".to_vec()),
+            reader.read_frame().expect("retried frame"),
+            "prefix should not be lost"
+        );
+    }
+}
@@ -0,0 +1,184 @@
+use std::io::Read;
+
+use super::BufferedMarkableReader;
+
+/// Reads lines of UTF-8 text as owned `String`s, with the line ending removed.
+/// Lines may end with either `\n` or `\r\n`, and the last line of the stream does
+/// not need a line ending.
+///
+/// Lines are read within a transaction, so a line that fails to decode is left
+/// unconsumed, and `peek_line` can look at the next line without consuming it.
+pub struct LineReader<R> {
+    reader: BufferedMarkableReader<R>,
+}
+
+impl<R> LineReader<R>
+where
+    R: Read,
+{
+    /// Creates a new line reader over `inner`
+    pub fn new(inner: R) -> LineReader<R> {
+        LineReader::from_reader(BufferedMarkableReader::new(inner))
+    }
+
+    /// Creates a new line reader using an existing reader, which allows its buffer
+    /// sizes and limits to be chosen
+    pub fn from_reader(reader: BufferedMarkableReader<R>) -> LineReader<R> {
+        LineReader { reader }
+    }
+
+    /// Reads the next line, or returns `None` at the end of the stream.
+    ///
+    /// Returns an `ErrorKind::InvalidData` error if the line is not valid UTF-8, in
+    /// which case the line is not consumed.
+    pub fn next_line(&mut self) -> std::io::Result<Option<String>> {
+        self.reader.transaction(read_line)
+    }
+
+    /// Reads the next line like `next_line`, but resets the stream afterwards so the
+    /// same line will be returned by the next call.
+    pub fn peek_line(&mut self) -> std::io::Result<Option<String>> {
+        let id = self.reader.push_mark();
+        let line = read_line(&mut self.reader);
+        self.reader.pop_mark(id);
+        line
+    }
+
+    /// Gets a reference to the underlying markable reader
+    pub fn get_ref(&self) -> &BufferedMarkableReader<R> {
+        &self.reader
+    }
+
+    /// Gets a mutable reference to the underlying markable reader
+    pub fn get_mut(&mut self) -> &mut BufferedMarkableReader<R> {
+        &mut self.reader
+    }
+
+    /// Returns the underlying markable reader, including any lines it has buffered
+    pub fn into_inner(self) -> BufferedMarkableReader<R> {
+        self.reader
+    }
+}
+
+/// Reads a line and strips its line ending, returning `None` at the end of the stream
+fn read_line<R>(reader: &mut BufferedMarkableReader<R>) -> std::io::Result<Option<String>>
+where
+    R: Read,
+{
+    let mut line = Vec::new();
+    if reader.read_until(b'\n', &mut line)? == 0 {
+        return Ok(None);
+    }
+
+    if line.ends_with(b"\n") {
+        line.pop();
+        if line.ends_with(b"\r") {
+            line.pop();
+        }
+    }
+
+    String::from_utf8(line)
+        .map(Some)
+        .map_err(|e| std::io::Error::new(std::io::ErrorKind::InvalidData, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use super::LineReader;
+
+    #[test]
+    fn test_lf_and_crlf_lines() {
+        let mut reader = LineReader::new(Cursor::new(b"first\r\nsecond\n\nthird\r\n".to_vec()));
+        assert_eq!(
+            Some("first".to_string()),
+            reader.next_line().expect("crlf line")
+        );
+        assert_eq!(
+            Some("second".to_string()),
+            reader.next_line().expect("lf line")
+        );
+        assert_eq!(Some(String::new()), reader.next_line().expect("empty line"));
+        assert_eq!(
+            Some("third".to_string()),
+            reader.next_line().expect("crlf line")
+        );
+        assert_eq!(
+            None,
+            reader.next_line().expect("end of stream"),
+            "no more lines"
+        );
+    }
+
+    #[test]
+    fn test_final_line_without_newline() {
+        let mut reader = LineReader::new(Cursor::new(b"one\ntwo\r".to_vec()));
+        assert_eq!(
+            Some("one".to_string()),
+            reader.next_line().expect("first line")
+        );
+        assert_eq!(
+            Some("two\r".to_string()),
+            reader.next_line().expect("final line"),
+            "only a full line ending should be stripped"
+        );
+        assert_eq!(
+            None,
+            reader.next_line().expect("end of stream"),
+            "no more lines"
+        );
+    }
+
+    #[test]
+    fn test_peek_line() {
+        let mut reader = LineReader::new(Cursor::new(b"header\nbody\n".to_vec()));
+        assert_eq!(
+            Some("header".to_string()),
+            reader.peek_line().expect("peeked line")
+        );
+        assert_eq!(
+            Some("header".to_string()),
+            reader.next_line().expect("same line")
+        );
+        assert_eq!(
+            Some("body".to_string()),
+            reader.peek_line().expect("peeked line")
+        );
+        assert_eq!(
+            Some("body".to_string()),
+            reader.next_line().expect("same line")
+        );
+        assert_eq!(
+            None,
+            reader.peek_line().expect("end of stream"),
+            "no more lines"
+        );
+    }
+
+    #[test]
+    fn test_invalid_utf8_line() {
+        let input_data = b"ok\n\xFF\xFE\nafter\n".to_vec();
+        let mut reader = LineReader::new(Cursor::new(input_data.clone()));
+        assert_eq!(
+            Some("ok".to_string()),
+            reader.next_line().expect("valid line")
+        );
+        assert_eq!(
+            std::io::ErrorKind::InvalidData,
+            reader.next_line().unwrap_err().kind(),
+            "invalid UTF-8 should be rejected"
+        );
+
+        let mut rest = Vec::new();
+        reader
+            .get_mut()
+            .read_to_end(&mut rest)
+            .expect("should be able to read the rest");
+        assert_eq!(
+            &input_data[3..],
+            rest,
+            "invalid line should not be consumed"
+        );
+    }
+}
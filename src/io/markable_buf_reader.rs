@@ -0,0 +1,291 @@
+use std::io::{BufRead, Read};
+
+use super::{buffer::Buffer, MarkBufferError, MarkerStream, DEFAULT_MARKER_BUFFER_SIZE};
+
+/// A markable reader for inner readers that are already buffered, such as a
+/// `std::io::BufReader`. Rather than keeping a read buffer of its own, it reads through
+/// the inner `fill_buf`/`consume`, so bytes are not copied into a second buffer. Only
+/// the bytes read while marked are copied, into the mark buffer, to be replayed after
+/// a `reset`.
+///
+/// Unlike the other readers, `read` returns `Ok(0)` at the end of the stream, as
+/// `BufRead` expects.
+pub struct MarkableBufReader<R> {
+    inner: R,
+    is_marked: bool,
+    mark_buffer: Buffer,
+    position: u64,
+}
+
+impl<R> MarkableBufReader<R>
+where
+    R: BufRead,
+{
+    /// Creates a new reader with an unbounded mark buffer
+    pub fn new(inner: R) -> MarkableBufReader<R> {
+        MarkableBufReader::with_mark_buffer(inner, Buffer::new(DEFAULT_MARKER_BUFFER_SIZE, None))
+    }
+
+    /// Creates a new reader with a limited mark buffer.
+    /// Any reads that exceed the provided limit will result in an `std::io::Error(ErrorKind::OutOfMemory)` error
+    pub fn new_with_limited_back_buffer(inner: R, limit: usize) -> MarkableBufReader<R> {
+        MarkableBufReader::with_mark_buffer(
+            inner,
+            Buffer::new(DEFAULT_MARKER_BUFFER_SIZE.min(limit), Some(limit)),
+        )
+    }
+
+    fn with_mark_buffer(inner: R, mark_buffer: Buffer) -> MarkableBufReader<R> {
+        MarkableBufReader {
+            inner,
+            is_marked: false,
+            mark_buffer,
+            position: 0,
+        }
+    }
+
+    /// Gets the number of bytes read from the stream so far, which moves back when the
+    /// stream is reset
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Gets a reference to the inner reader
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the inner reader. **IMPORTANT** reading directly
+    /// from the inner reader bypasses the mark buffer, which will desync the mark
+    /// from the inner reader
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Unwraps this reader, returning the inner reader. Any bytes awaiting replay
+    /// in the mark buffer are lost
+    pub fn into_inner(self) -> R {
+        self.inner
+    }
+}
+
+impl<R> Read for MarkableBufReader<R>
+where
+    R: BufRead,
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let available = self.fill_buf()?;
+        let bytes_read = available.len().min(buf.len());
+        buf[..bytes_read].copy_from_slice(&available[..bytes_read]);
+        self.consume(bytes_read);
+        Ok(bytes_read)
+    }
+}
+
+impl<R> BufRead for MarkableBufReader<R>
+where
+    R: BufRead,
+{
+    /// Returns the bytes awaiting replay after a `reset` if there are any, otherwise
+    /// the buffer of the inner reader. While marked, the inner buffer is cut short at
+    /// the limit of the mark buffer, and an `ErrorKind::OutOfMemory` error is returned
+    /// once the limit is reached.
+    fn fill_buf(&mut self) -> std::io::Result<&[u8]> {
+        if self.mark_buffer.len() > 0 {
+            return Ok(self.mark_buffer.unread_front(usize::MAX));
+        }
+
+        let available = self.inner.fill_buf()?;
+        let Some(limit) = self.mark_buffer.limit().filter(|_| self.is_marked) else {
+            return Ok(available);
+        };
+
+        let used = self.mark_buffer.stored_len();
+        if used >= limit && !available.is_empty() {
+            return Err(MarkBufferError::LimitExceeded {
+                used: used + available.len(),
+                limit,
+            }
+            .into());
+        }
+
+        Ok(&available[..available.len().min(limit - used)])
+    }
+
+    /// While marked, no more bytes are consumed than the mark buffer has room to
+    /// retain.
+    fn consume(&mut self, amt: usize) {
+        if self.mark_buffer.len() > 0 {
+            self.position += self.mark_buffer.skip(amt) as u64;
+            return;
+        }
+
+        let mut amt = amt;
+        if self.is_marked && amt > 0 {
+            // The inner buffer still holds the bytes being consumed, so this does
+            // not read from the inner reader
+            let available = self.inner.fill_buf().unwrap_or_default();
+            let room = self.mark_buffer.limit().map_or(usize::MAX, |limit| {
+                limit.saturating_sub(self.mark_buffer.stored_len())
+            });
+            amt = amt.min(available.len()).min(room);
+            if self.mark_buffer.record(&available[..amt]).is_err() {
+                amt = 0;
+            }
+        }
+        self.position += amt as u64;
+        self.inner.consume(amt);
+    }
+}
+
+impl<R> MarkerStream for MarkableBufReader<R> {
+    fn mark(&mut self) -> usize {
+        self.is_marked = true;
+        self.mark_buffer.purge_read()
+    }
+
+    fn reset(&mut self) {
+        if !self.is_marked {
+            return;
+        }
+
+        self.is_marked = false;
+        self.position -= self.mark_buffer.restart() as u64;
+    }
+
+    fn clear_buffer(&mut self) {
+        self.is_marked = false;
+        self.mark_buffer.clear();
+    }
+
+    fn is_marked(&self) -> bool {
+        self.is_marked
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{BufRead, BufReader, Cursor, Read};
+
+    use crate::io::MarkerStream;
+
+    use super::MarkableBufReader;
+
+    #[test]
+    fn test_mark_and_reset() {
+        let input_data: Vec<u8> = (0..32).collect();
+        let inner = BufReader::with_capacity(8, Cursor::new(input_data.clone()));
+        let mut reader = MarkableBufReader::new(inner);
+        let mut buf = vec![0; 4];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 4 bytes");
+
+        reader.mark();
+        let mut marked = vec![0; 10];
+        reader
+            .read_exact(&mut marked)
+            .expect("should read across inner refills");
+        assert_eq!(input_data[4..14], marked, "should read the marked bytes");
+
+        reader.reset();
+        assert_eq!(
+            4,
+            reader.position(),
+            "position should move back to the mark"
+        );
+        let mut rest = Vec::new();
+        reader
+            .read_to_end(&mut rest)
+            .expect("should be able to read the rest");
+        assert_eq!(input_data[4..], rest, "should replay and then continue");
+    }
+
+    #[test]
+    fn test_reads_lines_through_buf_read() {
+        let inner = BufReader::new(Cursor::new(b"first\nsecond\n".to_vec()));
+        let mut reader = MarkableBufReader::new(inner);
+        reader.mark();
+        let mut line = String::new();
+        reader
+            .read_line(&mut line)
+            .expect("should be able to read a line");
+        assert_eq!("first\n", line, "should read the first line");
+
+        reader.reset();
+        let lines: Vec<String> = reader.lines().map(|line| line.unwrap()).collect();
+        assert_eq!(
+            vec!["first", "second"],
+            lines,
+            "the first line should replay"
+        );
+    }
+
+    #[test]
+    fn test_does_not_copy_inner_buffer() {
+        let inner = BufReader::new(Cursor::new((0..32).collect::<Vec<u8>>()));
+        let mut reader = MarkableBufReader::new(inner);
+        let served = reader.fill_buf().expect("should be able to fill").as_ptr();
+        assert_eq!(
+            reader.get_ref().buffer().as_ptr(),
+            served,
+            "unmarked bytes should be served from the inner buffer"
+        );
+
+        reader.mark();
+        let served = reader.fill_buf().expect("should be able to fill").as_ptr();
+        assert_eq!(
+            reader.get_ref().buffer().as_ptr(),
+            served,
+            "marked bytes should be served from the inner buffer too"
+        );
+    }
+
+    #[test]
+    fn test_consume_after_marking_past_limit() {
+        let inner = BufReader::new(Cursor::new((0..32).collect::<Vec<u8>>()));
+        let mut reader = MarkableBufReader::new_with_limited_back_buffer(inner, 4);
+        let available = reader.fill_buf().expect("should be able to fill").len();
+        reader.mark();
+        reader.consume(available);
+        assert_eq!(4, reader.position(), "should consume only up to the limit");
+
+        reader.reset();
+        let mut buf = vec![0; 6];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read after the reset");
+        assert_eq!(vec![0, 1, 2, 3, 4, 5], buf, "no bytes should be lost");
+    }
+
+    #[test]
+    fn test_limit_exceeded() {
+        let inner = BufReader::with_capacity(4, Cursor::new(vec![0; 16]));
+        let mut reader = MarkableBufReader::new_with_limited_back_buffer(inner, 6);
+        reader.mark();
+        let mut buf = vec![0; 6];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read up to the limit");
+        let err = reader.read(&mut buf).expect_err("should stop at the limit");
+        assert_eq!(
+            std::io::ErrorKind::OutOfMemory,
+            err.kind(),
+            "should be out of memory"
+        );
+
+        reader.reset();
+        reader
+            .read_exact(&mut buf)
+            .expect("the marked bytes should still replay");
+    }
+
+    #[test]
+    fn test_send_and_sync() {
+        fn assert_send<T: Send>() {}
+        fn assert_sync<T: Sync>() {}
+
+        assert_send::<MarkableBufReader<std::io::BufReader<Cursor<Vec<u8>>>>>();
+        assert_sync::<MarkableBufReader<std::io::BufReader<Cursor<Vec<u8>>>>>();
+    }
+}
@@ -1,6 +1,15 @@
-use std::io::Write;
+use std::{
+    collections::HashMap,
+    io::{Cursor, Write},
+    sync::{Arc, Mutex, PoisonError},
+};
 
-use super::{buffer::Buffer, MarkerStream, DEFAULT_MARKER_BUFFER_SIZE};
+use super::{
+    aligned_len, buffer::Buffer, doubling_limit, seek_before_inner_start, Bom,
+    BufferedMarkableReader, ConsumeHook, LimitHook, MarkBufferError, MarkId, MarkToken,
+    MarkerStream, NestedMark, ReadError, ReaderSnapshot, SeekableInner, DEFAULT_BUFFER_SIZE,
+    DEFAULT_MARKER_BUFFER_SIZE,
+};
 
 /// Reads bytes from the inner source with the additional ability
 /// to `mark` a stream at a point that can be returned to later
@@ -13,11 +22,34 @@ use super::{buffer::Buffer, MarkerStream, DEFAULT_MARKER_BUFFER_SIZE};
 /// If the inner stream should also be buffered, use `BufferedMarkableStream`,
 /// which may offer a slight optimization over passing a `std::io::BufReader`
 /// as the inner reader to this stream.
+///
+/// When `R: Clone` the reader can be cloned, which copies all of the buffered
+/// state along with the inner reader. Note that cloning a reader over a `File`
+/// clones the handle, which shares its position with the original, so only
+/// in-memory readers (e.g., `Cursor`) can be explored independently.
+#[derive(Clone)]
 pub struct MarkableReader<R> {
     inner: R,
     inner_complete: bool,
     is_marked: bool,
     mark_buffer: Buffer,
+    peek_buffer: Buffer,
+    scratch: Vec<u8>,
+    inner_offset: u64,
+    read_alignment: Option<usize>,
+    recording: Option<Buffer>,
+    position: u64,
+    nested_marks: Vec<NestedMark>,
+    named_marks: HashMap<String, usize>,
+    total_discarded: u64,
+    on_limit: Option<LimitHook>,
+    sliding_window: bool,
+    mark_invalidated: bool,
+    sticky_mark: bool,
+    on_consume: Option<ConsumeHook>,
+    consumed_to: u64,
+    reclaim_after_replay: bool,
+    mark_generation: u64,
 }
 
 impl<R> MarkableReader<R>
@@ -34,12 +66,7 @@ where
     // // now use anywhere you would use a standard reader
     // ```
     pub fn new(inner: R) -> MarkableReader<R> {
-        MarkableReader {
-            inner,
-            inner_complete: false,
-            is_marked: false,
-            mark_buffer: Buffer::new(DEFAULT_MARKER_BUFFER_SIZE, None),
-        }
+        MarkableReader::with_mark_buffer(inner, Buffer::new(DEFAULT_MARKER_BUFFER_SIZE, None))
     }
 
     /// Creates a new reader with an limited marked buffer
@@ -54,12 +81,23 @@ where
     // // now use anywhere you would use a standard reader
     // ```
     pub fn new_with_limited_back_buffer(inner: R, limit: usize) -> MarkableReader<R> {
-        MarkableReader {
+        MarkableReader::with_mark_buffer(
             inner,
-            inner_complete: false,
-            is_marked: false,
-            mark_buffer: Buffer::new(DEFAULT_MARKER_BUFFER_SIZE, Some(limit)),
-        }
+            Buffer::new(DEFAULT_MARKER_BUFFER_SIZE, Some(limit)),
+        )
+    }
+
+    /// Creates a new reader whose mark buffer limit starts at `initial` and doubles as
+    /// needed, up to `max`. Only reads that would take the mark buffer past `max` result
+    /// in an `std::io::Error(ErrorKind::OutOfMemory)` error. The growth is driven by an
+    /// `on_limit` callback, so registering another one replaces it.
+    pub fn new_with_growable_limit(inner: R, initial: usize, max: usize) -> MarkableReader<R> {
+        let mut reader = MarkableReader::with_mark_buffer(
+            inner,
+            Buffer::new(DEFAULT_MARKER_BUFFER_SIZE.min(initial), Some(initial)),
+        );
+        reader.on_limit(doubling_limit(max));
+        reader
     }
 
     /// Creates a new reader using the provided capacities as the initial capacity and limit.
@@ -77,238 +115,6159 @@ where
         capacity: usize,
         limit: usize,
     ) -> MarkableReader<R> {
+        MarkableReader::with_mark_buffer(inner, Buffer::new(capacity, Some(limit)))
+    }
+
+    /// Creates a new reader that serves `prefix` before reading from `inner`, for
+    /// handing over bytes that were already read from the stream (e.g., to sniff the
+    /// protocol). The prefix is treated as part of the stream, so it is counted by
+    /// `position` and replayed after a `reset` like any other marked read.
+    pub fn with_prefix(inner: R, prefix: Vec<u8>) -> MarkableReader<R> {
+        let mut reader = MarkableReader::new(inner);
+        reader
+            .peek_buffer
+            .append(&prefix)
+            .expect("peek buffer is unbounded");
+        reader
+    }
+
+    fn with_mark_buffer(inner: R, mark_buffer: Buffer) -> MarkableReader<R> {
         MarkableReader {
             inner,
             inner_complete: false,
             is_marked: false,
-            mark_buffer: Buffer::new(capacity, Some(limit)),
+            mark_buffer,
+            peek_buffer: Buffer::new(0, None),
+            scratch: Vec::new(),
+            inner_offset: 0,
+            read_alignment: None,
+            recording: None,
+            position: 0,
+            nested_marks: Vec::new(),
+            named_marks: HashMap::new(),
+            total_discarded: 0,
+            on_limit: None,
+            sliding_window: false,
+            mark_invalidated: false,
+            sticky_mark: false,
+            on_consume: None,
+            consumed_to: 0,
+            reclaim_after_replay: false,
+            mark_generation: 0,
         }
     }
 
-    /// Returns the inner reader. **IMPORTANT** this will likely result in data loss
-    /// of whatever data has been read into the buffer
-    pub fn into_inner(self) -> R {
-        self.inner
+    /// Aligns reads from the inner reader so that each begins at an offset, relative
+    /// to where the inner reader was when it was wrapped, that is a multiple of `align`.
+    /// Reads are extended to the next boundary and the excess is held until requested.
+    ///
+    /// The only read that may begin unaligned is one that follows a short read,
+    /// which occurs when checking the inner reader for the end of the stream.
+    ///
+    /// # Panics
+    /// Panics if `align` is 0
+    pub fn with_read_alignment(mut self, align: usize) -> MarkableReader<R> {
+        assert!(align > 0, "read alignment must be greater than 0");
+        self.read_alignment = Some(align);
+        self
     }
 
-    /// Reads at most `buf.len()` bytes from the underlying buffers to fill the provided buffer.
-    fn read_into_buf(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        // If marked, then we only read from the read buffer and all
-        // read bytes go in the mark buffer.
-        // If not marked, we read what we can from the mark buffer and then read the remaining
-        // bytes from the underlying reader.
+    /// Records every byte read from the inner reader, from this point forward, so the
+    /// complete stream can later be replayed from the start using `replay`.
+    ///
+    /// **IMPORTANT** the recording is never discarded, so it grows to the size of
+    /// the entire stream. Use `with_limited_recording` to cap its size.
+    pub fn with_recording(mut self) -> MarkableReader<R> {
+        self.recording = Some(Buffer::new(DEFAULT_MARKER_BUFFER_SIZE, None));
+        self
+    }
+
+    /// Records every byte read from the inner reader, from this point forward, so the
+    /// complete stream can later be replayed from the start using `replay`.
+    /// Reads from the inner reader stop short at the limit, and once the recording is
+    /// full, any read that needs the inner reader will result in an
+    /// `std::io::Error(ErrorKind::OutOfMemory)` error without consuming anything
+    pub fn with_limited_recording(mut self, limit: usize) -> MarkableReader<R> {
+        self.recording = Some(Buffer::new(
+            DEFAULT_MARKER_BUFFER_SIZE.min(limit),
+            Some(limit),
+        ));
+        self
+    }
+
+    /// Returns a new reader over everything recorded from the inner reader, starting
+    /// from the beginning of the recording. If recording was not enabled, the returned
+    /// reader will be empty.
+    ///
+    /// The recording includes bytes that have been read from the inner reader
+    /// but not yet returned by this reader (e.g., peeked bytes).
+    pub fn replay(&self) -> MarkableReader<Cursor<Vec<u8>>> {
+        let recorded = self
+            .recording
+            .as_ref()
+            .map(|recording| recording.to_vec())
+            .unwrap_or_default();
+        MarkableReader::new(Cursor::new(recorded))
+    }
+
+    /// Returns the number of bytes that have been returned by this reader. Bytes that
+    /// are replayed after a `reset` are only counted once, so this reflects the
+    /// current position within the stream.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Returns the total number of buffered bytes that have been discarded over the
+    /// lifetime of this reader by `mark`, `clear_buffer`, and committing the outermost
+    /// nested mark. This is useful for tuning the limit of the mark buffer.
+    pub fn total_discarded(&self) -> u64 {
+        self.total_discarded
+    }
+
+    /// Returns whether the stream is currently marked
+    pub fn is_marked(&self) -> bool {
+        self.is_marked
+    }
+
+    /// Returns the number of bytes retained in the mark buffer for replay, or 0 if the
+    /// stream is not marked. For a reader with a limited back buffer, reads that would
+    /// take this past the limit will fail with `ErrorKind::OutOfMemory`.
+    pub fn buffered_len(&self) -> usize {
         if self.is_marked {
-            // First grab what we can from the mark buffer
-            let buffer_bytes_read = self.mark_buffer.read_into(buf, 0);
-            // Then fill and retain remaining from the inner reader
-            let inner_bytes_read =
-                self.read_data_into_buf_and_marked_stream(buf, buffer_bytes_read)?;
-            Ok(inner_bytes_read + buffer_bytes_read)
+            self.mark_buffer.stored_len()
         } else {
-            // Otherwise, read what we can from the mark buffer and then go to inner reader
-            // for any remaining bytes
-            let mut bytes_read = self.mark_buffer.read_into(buf, 0);
-            bytes_read += self.fill_from_inner(buf, bytes_read)?;
-
-            if bytes_read == 0 {
-                Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof))
-            } else {
-                Ok(bytes_read)
-            }
+            0
         }
     }
 
-    /// Fills the provided buffer with bytes from the underlying stream and also places those
-    /// bytes into the mark buffer
-    fn read_data_into_buf_and_marked_stream(
-        &mut self,
-        buf: &mut [u8],
-        offset: usize,
-    ) -> std::io::Result<usize> {
-        let inner_bytes_read = self.fill_from_inner(buf, offset)?;
-        if inner_bytes_read > 0 {
-            // Inner the inner bytes read will be last n bytes that were read from into the buffer
-            let inner_bytes = &buf[buf.len() - inner_bytes_read..buf.len()];
-            self.mark_buffer.write(inner_bytes)?;
+    /// Gets a reference to the inner reader
+    pub fn get_ref(&self) -> &R {
+        &self.inner
+    }
+
+    /// Gets a mutable reference to the inner reader. **IMPORTANT** reading directly
+    /// from the inner reader bypasses the buffers of this reader, which will desync
+    /// any buffered bytes and the mark from the inner reader
+    pub fn get_mut(&mut self) -> &mut R {
+        &mut self.inner
+    }
+
+    /// Sets the limit of the mark buffer, or removes it if `None`. Any reads that
+    /// exceed the limit will result in an `std::io::Error(ErrorKind::OutOfMemory)` error.
+    ///
+    /// Returns an `ErrorKind::InvalidInput` error, leaving the limit unchanged, if the
+    /// mark buffer already retains more bytes than the new limit allows.
+    pub fn set_mark_limit(&mut self, limit: Option<usize>) -> std::io::Result<()> {
+        if !self.is_marked {
+            self.mark_buffer.purge_read();
         }
 
-        Ok(inner_bytes_read)
+        self.mark_buffer.set_limit(limit)
     }
 
-    /// Fills the provided buffer with bytes from the read buffer starting with at the provided offset
-    fn fill_from_inner(&mut self, buf: &mut [u8], offset: usize) -> std::io::Result<usize> {
-        if self.inner_complete {
-            return Ok(0);
+    /// Registers a callback invoked with `(used, limit)` just before retaining marked
+    /// bytes would take the mark buffer past its limit, where `used` is the number of
+    /// bytes it would need to hold. Returning `Some(new_limit)` raises the limit and lets
+    /// the read proceed if the new limit is large enough, while returning `None` leaves
+    /// the limit in place and the read fails with `ErrorKind::OutOfMemory`. Clones of
+    /// this reader share the callback.
+    pub fn on_limit<F>(&mut self, f: F)
+    where
+        F: FnMut(usize, usize) -> Option<usize> + Send + 'static,
+    {
+        self.on_limit = Some(Arc::new(Mutex::new(f)));
+    }
+
+    /// Registers a callback invoked with the bytes of the stream as they are consumed,
+    /// such as for keeping a running checksum. Each byte is passed only the first time
+    /// it is read, so bytes replayed after a `reset`, or read again after seeking back,
+    /// are not counted twice and the callback sees the stream in order. Peeked bytes
+    /// are passed once they are read, while bytes jumped over by seeking forward are
+    /// never passed. Clones of this reader share the callback.
+    pub fn on_consume<F>(&mut self, f: F)
+    where
+        F: FnMut(&[u8]) + Send + 'static,
+    {
+        self.on_consume = Some(Arc::new(Mutex::new(f)));
+    }
+
+    /// Enables or disables the sliding window mode for a limited mark buffer. By default,
+    /// a marked read that would exceed the limit fails with `ErrorKind::OutOfMemory`.
+    /// With the sliding window enabled, the oldest marked bytes are dropped to make room
+    /// instead, and the mark is invalidated as it can no longer be returned to: a later
+    /// `checked_reset` fails with `ErrorKind::InvalidData`, while `reset` unmarks the
+    /// stream without replaying anything. Nested and named marks that pointed into the
+    /// dropped bytes are discarded.
+    pub fn set_sliding_window(&mut self, enabled: bool) {
+        self.sliding_window = enabled;
+    }
+
+    /// Enables or disables sticky marks. By default, `reset` returns to the mark and
+    /// unmarks the stream, so the replayed bytes are read once more and then released.
+    /// With sticky marks enabled, `reset` behaves like `reset_keep_mark`: the stream
+    /// stays marked and everything read since the original mark is retained, so each
+    /// `reset` returns to the same place until `clear_buffer` or a new `mark`. This
+    /// suits tokenizers that make several passes, but `buffered_len` keeps growing as
+    /// new bytes are read.
+    pub fn set_sticky_mark(&mut self, enabled: bool) {
+        self.sticky_mark = enabled;
+    }
+
+    /// Pre-allocates room in the mark buffer for at least `additional` more bytes, so a
+    /// large marked read does not need to grow the buffer as it goes. The reservation
+    /// is capped at the limit of the mark buffer, if one is set.
+    pub fn reserve(&mut self, additional: usize) {
+        if !self.is_marked {
+            self.mark_buffer.purge_read();
         }
 
-        let mut read = 0;
-        let mut single_byte_buf = vec![0; 1];
-        while read + offset < buf.len() {
-            let current_read = self.inner.read(&mut single_byte_buf)?;
-            if current_read > 0 {
-                buf[read + offset] = single_byte_buf[0];
-                read += 1;
-            } else {
-                return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof));
-            }
+        self.mark_buffer.reserve_retained(additional);
+    }
+
+    /// Reads like `read`, but reports reaching the limit of the mark buffer as a typed
+    /// `MarkBufferError::LimitExceeded` carrying the sizes involved, rather than an
+    /// `ErrorKind::OutOfMemory` error that looks like a failed allocation
+    pub fn try_read_marked(&mut self, buf: &mut [u8]) -> Result<usize, MarkBufferError> {
+        self.read_into_buf(buf).map_err(MarkBufferError::from)
+    }
+
+    /// Reads like `read`, but classifies the outcome so callers can branch on it:
+    /// `ReadError::Eof` when the stream has ended, `ReadError::LimitExceeded` when the
+    /// mark buffer is full, and `ReadError::Io` for any other error. Reading into an
+    /// empty `buf` returns `Ok(0)` rather than `Eof`.
+    pub fn checked_read(&mut self, buf: &mut [u8]) -> Result<usize, ReadError> {
+        match self.read_into_buf(buf) {
+            Ok(0) if !buf.is_empty() => Err(ReadError::Eof),
+            Ok(bytes_read) => Ok(bytes_read),
+            Err(err) => Err(MarkBufferError::from(err).into()),
         }
+    }
 
-        Ok(read)
+    /// Gets the limit of the mark buffer, or `None` if it is unbounded
+    pub fn mark_limit(&self) -> Option<usize> {
+        self.mark_buffer.limit()
     }
-}
 
-impl<R> MarkerStream for MarkableReader<R> {
-    /// Marks the location of the inner stream. From tis point forward
-    /// reads will be cached. If the stream was marked prior to this call
-    /// the current buffer will be discarded.
-    ///
-    /// Returns the number of bytes that were discarded as a result of this operation
-    fn mark(&mut self) -> usize {
-        self.is_marked = true;
-        self.mark_buffer.purge_read()
+    /// Gets the number of bytes the mark buffer can hold without reallocating
+    pub fn mark_capacity(&self) -> usize {
+        self.mark_buffer.capacity()
     }
 
-    /// Resets the stream previously marked position, if it is set.
-    /// If the reader was not previously marked, this has no affect.
-    ///
-    fn reset(&mut self) {
-        self.is_marked = false;
-        self.mark_buffer.restart();
+    /// Gets how many more bytes can be read while marked before the mark buffer limit
+    /// is reached and reads fail with `ErrorKind::OutOfMemory`, or `None` if the mark
+    /// buffer is unbounded. When the stream is not marked, this is the headroom a new
+    /// mark would start with.
+    pub fn remaining_limit(&self) -> Option<usize> {
+        let used = if self.is_marked {
+            self.mark_buffer.stored_len()
+        } else {
+            self.mark_buffer.len()
+        };
+
+        self.mark_buffer
+            .limit()
+            .map(|limit| limit.saturating_sub(used))
     }
 
-    fn clear_buffer(&mut self) {
-        self.is_marked = false;
-        self.mark_buffer.clear();
+    /// Releases the memory held by the mark buffer if it has no bytes left to replay
+    /// and the stream is not marked, which is useful for long-lived readers after a
+    /// large marked region has been replayed. This may cause the mark buffer to be
+    /// reallocated the next time the stream is marked.
+    pub fn reclaim(&mut self) {
+        if self.is_marked {
+            return;
+        }
+
+        self.mark_buffer.purge_read();
+        if self.mark_buffer.stored_len() == 0 {
+            self.mark_buffer.shrink_to_fit();
+        }
     }
-}
 
-impl<R> std::io::Read for MarkableReader<R>
-where
-    R: std::io::Read,
-{
-    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
-        self.read_into_buf(buf)
+    /// Returns the inner reader. **IMPORTANT** this will likely result in data loss
+    /// of whatever data has been read into the buffer. Use `into_parts` to also
+    /// recover the buffered bytes.
+    pub fn into_inner(self) -> R {
+        self.inner
     }
-}
 
-impl<R> From<R> for MarkableReader<R>
-where
-    R: std::io::Read,
-{
-    fn from(value: R) -> Self {
-        MarkableReader::new(value)
+    /// Returns the inner reader along with the bytes that have been buffered but not yet
+    /// read, in the order they would have been read. Reading the returned bytes followed
+    /// by the inner reader continues the stream where this reader left off.
+    pub fn into_parts(self) -> (R, Vec<u8>) {
+        let mut buffered = self.mark_buffer.to_vec();
+        buffered.extend(self.peek_buffer.to_vec());
+        (self.inner, buffered)
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::io::{Cursor, Read};
+    /// Reads directly from the inner reader, bypassing all buffering. To avoid
+    /// desynchronizing the stream, the read is refused with `Ok(None)` if the
+    /// reader is marked or holds any buffered bytes that have not been read.
+    /// Otherwise returns `Ok(Some(n))` with the number of bytes read.
+    ///
+    /// With a read alignment set, the read is shortened so it ends on an alignment
+    /// boundary, and refused with `Ok(None)` if `buf` is too small to reach one.
+    pub fn read_inner_direct(&mut self, buf: &mut [u8]) -> std::io::Result<Option<usize>> {
+        if self.is_marked || self.mark_buffer.len() > 0 || self.peek_buffer.len() > 0 {
+            return Ok(None);
+        }
 
-    use crate::io::MarkerStream;
+        let length = self.direct_read_len(buf.len());
+        if length == 0 && !buf.is_empty() {
+            return Ok(None);
+        }
 
-    use super::MarkableReader;
+        let bytes_read = self.read_inner(&mut buf[..length])?;
+        self.advance_position(&buf[..bytes_read]);
+        Ok(Some(bytes_read))
+    }
 
-    #[test]
-    fn test_basic_read() {
-        let input_data = vec![0, 1, 2, 3];
-        let data = Cursor::new(input_data.clone());
-        let mut reader = MarkableReader::new(data);
+    /// Reads until `buf` is full or the end of the stream is reached, returning the
+    /// number of bytes read. Unlike `read_exact`, a short final read is not an error,
+    /// so partial trailing data can be recovered; the count is less than `buf.len()`
+    /// only at the end of the stream.
+    pub fn read_exact_or_n(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.read_into_buf(&mut buf[filled..])? {
+                0 => break,
+                bytes_read => filled += bytes_read,
+            }
+        }
 
-        let mut read_buf = vec![0; input_data.len()];
-        reader
-            .read_exact(&mut read_buf)
-            .expect("should be able to read bytes back");
-        assert_eq!(
-            input_data, read_buf,
-            "read buffer and input buffer should match"
-        );
+        Ok(filled)
     }
 
-    #[test]
-    fn test_marked_read() {
-        let input_data = vec![0, 1, 2, 3];
-        let data = Cursor::new(input_data.clone());
-        let mut reader = MarkableReader::new(data);
+    /// Reads exactly `N` bytes into a fixed-size array, such as a magic number or
+    /// another fixed-width field, without allocating. Returns an
+    /// `ErrorKind::UnexpectedEof` error if the stream ends first; on a marked stream
+    /// the failed read can be undone with `reset`.
+    pub fn read_array<const N: usize>(&mut self) -> std::io::Result<[u8; N]> {
+        let mut bytes = [0; N];
+        std::io::Read::read_exact(self, &mut bytes)?;
+        Ok(bytes)
+    }
 
-        let mut single_byte_buf = vec![0];
-        reader
-            .read_exact(&mut single_byte_buf)
-            .expect("should be able to read single byte");
+    /// Looks for the next occurrence of `needle` within the next `max_scan` bytes,
+    /// returning its offset from the current position without consuming anything. A
+    /// match must lie wholly within the scanned bytes. The scanned bytes are peeked, so
+    /// they are held in the lookahead buffer but do not count towards the mark limit.
+    ///
+    /// Returns `Ok(None)` if there is no match before `max_scan` bytes or the end of
+    /// the stream. Pass `usize::MAX` to scan to the end of the stream.
+    pub fn find(&mut self, needle: &[u8], max_scan: usize) -> std::io::Result<Option<usize>> {
+        if needle.is_empty() {
+            return Ok(Some(0));
+        }
 
-        assert_eq!(0, reader.mark(), "no bytes should be wasted");
+        let scanned = self.peek(max_scan)?;
+        Ok(scanned
+            .windows(needle.len())
+            .position(|window| window == needle))
+    }
 
-        let mut rest_of_buf = vec![0; input_data.len() - 1];
-        reader
-            .read_exact(&mut rest_of_buf)
-            .expect("should be able to read rest of buffer");
+    /// Copies bytes starting `offset` bytes ahead of the current position into `buf`
+    /// without consuming anything, reading from the inner reader as needed. The bytes
+    /// are peeked, so everything up to the end of the copy is held in the lookahead
+    /// buffer, which suits parsing tables that are looked up ahead of the read cursor.
+    ///
+    /// Returns the number of bytes copied, which is less than `buf.len()` only if the
+    /// end of the stream is reached first, and 0 if `offset` is past the end.
+    pub fn read_at(&mut self, offset: usize, buf: &mut [u8]) -> std::io::Result<usize> {
+        let peeked = self.peek(offset.saturating_add(buf.len()))?;
+        let Some(available) = peeked.get(offset..) else {
+            return Ok(0);
+        };
+        buf[..available.len()].copy_from_slice(available);
+        Ok(available.len())
+    }
 
-        reader.reset();
-        rest_of_buf = vec![0; input_data.len() - 1];
+    /// Copies as many already buffered bytes as fit into `buf`, first any awaiting
+    /// replay and then those in the peek buffer, without ever reading from the inner
+    /// reader. While marked, the bytes are retained like any other read, so no more are
+    /// copied than the mark buffer limit allows
+    /// unless a sliding window drops the oldest marked bytes to make room.
+    ///
+    /// Returns the number of bytes copied, which is 0 if nothing is buffered.
+    pub fn drain_buffered(&mut self, buf: &mut [u8]) -> usize {
+        let replayed = self.mark_buffer.read_into(buf, 0);
+        let mut lookahead_len = self.peek_buffer.len().min(buf.len() - replayed);
+        if self.is_marked && !self.sliding_window {
+            lookahead_len = lookahead_len.min(self.remaining_limit().unwrap_or(usize::MAX));
+        }
 
-        reader
-            .read_exact(&mut rest_of_buf)
-            .expect("should be able to read rest of buffer again after reset");
+        let target = &mut buf[replayed..replayed + lookahead_len];
+        let mut from_lookahead = self.peek_buffer.read_into(target, 0);
+        if self.is_marked && self.retain_marked(&target[..from_lookahead]).is_err() {
+            // an `on_limit` callback can still refuse the bytes, so they stay buffered
+            self.peek_buffer
+                .rewind_to(self.peek_buffer.consumed() - from_lookahead);
+            from_lookahead = 0;
+        }
 
-        assert_eq!(
-            input_data[1..],
-            rest_of_buf,
-            "buffer should be last 3 bytes"
-        );
+        let drained = replayed + from_lookahead;
+        self.advance_position(&buf[..drained]);
+        drained
     }
 
-    #[test]
-    fn test_back_buffer_and_read_buffer_read() {
-        let input_data = vec![0, 1, 2, 3];
-        let data = Cursor::new(input_data.clone());
-        let mut reader = MarkableReader::new(data);
+    /// Reads into `buf` without staging the bytes in the internal buffers. Bytes that
+    /// are already buffered are drained first, and only once they are used up does the
+    /// read go directly from the inner reader into `buf`, avoiding an intermediate copy
+    /// for bulk transfers. While marked, this behaves like a normal `read` so the bytes
+    /// are still retained for a `reset`.
+    ///
+    /// With a read alignment set, the direct read is shortened so it ends on an
+    /// alignment boundary. If `buf` is too small to reach one, it is filled by a
+    /// normal `read` instead.
+    ///
+    /// Returns `Ok(0)` at the end of the stream or if `buf` is empty.
+    pub fn read_passthrough(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if self.is_marked {
+            return self.read_into_buf(buf);
+        }
 
-        let mut half_buf = vec![0; input_data.len() / 2];
-        reader.mark();
-        reader
-            .read_exact(&mut half_buf)
-            .expect("should be able to read half the buffer");
+        let drained = self.drain_buffered(buf);
+        if drained > 0 || buf.is_empty() {
+            return Ok(drained);
+        }
 
-        reader.reset();
-        let mut whole_buf = vec![0; input_data.len()];
+        let length = self.direct_read_len(buf.len());
+        if length == 0 {
+            return self.read_into_buf(buf);
+        }
 
-        reader
-            .read_exact(&mut whole_buf)
-            .expect("should be able to whole buffer");
+        let bytes_read = self.read_inner(&mut buf[..length])?;
+        self.advance_position(&buf[..bytes_read]);
+        Ok(bytes_read)
+    }
 
-        assert_eq!(
-            input_data, whole_buf,
-            "input data and whole buf should match"
-        );
+    /// Returns the bytes that can be read next without copying, like
+    /// `std::io::BufRead::fill_buf`: those awaiting replay after a `reset` if there are
+    /// any, otherwise the lookahead buffer, which is refilled from the inner reader if it
+    /// is empty. Pair it with `consume` to advance past the bytes used. An empty slice
+    /// means the end of the stream.
+    ///
+    /// While marked, the slice is cut short at the limit of the mark buffer, after the
+    /// `on_limit` callback has had a chance to raise it, and an `ErrorKind::OutOfMemory`
+    /// error is returned once the limit is reached.
+    pub fn available(&mut self) -> std::io::Result<&[u8]> {
+        if self.mark_buffer.len() > 0 {
+            return Ok(self.mark_buffer.unread_front(usize::MAX));
+        }
+
+        self.peek(1)?;
+        let mut len = self.peek_buffer.len();
+        if self.is_marked && !self.sliding_window {
+            let used = self.mark_buffer.stored_len();
+            self.consult_on_limit(used + len)?;
+            if let Some(limit) = self.mark_buffer.limit() {
+                if used >= limit && len > 0 {
+                    return Err(MarkBufferError::LimitExceeded {
+                        used: used + len,
+                        limit,
+                    }
+                    .into());
+                }
+                len = len.min(limit - used);
+            }
+        }
+
+        Ok(self.peek_buffer.unread_front(len))
     }
 
-    #[test]
-    fn test_read_with_popping_bytes() {
-        let input_data = vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
-        let data = Cursor::new(input_data.clone());
-        let mut reader = MarkableReader::new(data);
-        let mut single_byte_buffer = vec![0_u8; 1];
+    /// Advances past `amt` bytes returned by `available`, like
+    /// `std::io::BufRead::consume`. While marked, the bytes are retained so a `reset`
+    /// replays them. No more than are buffered are consumed, and while marked no more
+    /// than the mark buffer limit allows.
+    ///
+    /// The bytes are skipped without being copied, apart from those copied into the
+    /// mark buffer to be retained.
+    pub fn consume(&mut self, amt: usize) {
+        let mut remaining = amt - self.skip_unretained(amt);
+        let mut chunk = [0; 256];
+        while remaining > 0 {
+            let len = remaining.min(chunk.len());
+            let drained = self.drain_buffered(&mut chunk[..len]);
+            if drained == 0 {
+                break;
+            }
 
-        for i in 0..input_data.len() - 1 {
-            reader.mark();
-            let expected = input_data[i..i + 2].to_vec();
-            let mut actual = [0_u8; 2];
-            reader
-                .read_exact(&mut actual)
-                .expect("should always be able to read 2 bytes");
-            assert_eq!(
-                expected, actual,
-                "bytes at index {i} should be {expected:?} but were {actual:?}"
-            );
+            remaining -= drained;
+        }
+    }
 
-            reader.reset();
-            reader
-                .read_exact(&mut single_byte_buffer)
-                .expect("should be able to read single byte");
-            assert_eq!(
-                single_byte_buffer[0], input_data[i],
-                "popped byte at index {i} should be {i} but was {}",
-                single_byte_buffer[0]
-            );
+    /// Returns whether the stream has no more bytes to read, including bytes still
+    /// awaiting replay after a `reset`. This may read from the inner reader to find
+    /// out, but does not consume anything.
+    pub fn is_eof(&mut self) -> std::io::Result<bool> {
+        if self.mark_buffer.len() > 0 || self.peek_buffer.len() > 0 {
+            return Ok(false);
+        }
+
+        Ok(self.peek(1)?.is_empty())
+    }
+
+    /// Returns up to the next `n` bytes of the stream without consuming them,
+    /// reading from the inner reader as needed. The returned slice is shorter
+    /// than `n` only if the end of the stream is reached first.
+    ///
+    /// Unlike `mark`/`reset`, no further call is required to return to the
+    /// current position; the next read will start with the peeked bytes.
+    pub fn peek(&mut self, n: usize) -> std::io::Result<&[u8]> {
+        self.move_replay_into_peek_buffer()?;
+
+        while self.peek_buffer.len() < n && !self.inner_complete {
+            self.fill_peek_buffer(n - self.peek_buffer.len())?;
         }
+
+        let available = self.peek_buffer.as_slice();
+        Ok(&available[0..n.min(available.len())])
+    }
+
+    /// Returns up to the next `k` bytes of the stream for lookahead in LL(k) style
+    /// parsers. Like `peek`, nothing is consumed and no `reset` is needed: the next read
+    /// starts with these bytes. The slice holds exactly `k` bytes unless the end of the
+    /// stream is reached first, and a later `lookahead` with a smaller `k` returns a
+    /// prefix of it. Any mark the caller has set is left as it is.
+    pub fn lookahead(&mut self, k: usize) -> std::io::Result<&[u8]> {
+        self.peek(k)
+    }
+
+    /// Returns exactly the next `n` bytes of the stream without consuming them,
+    /// reading from the inner reader as needed.
+    ///
+    /// Returns an `ErrorKind::UnexpectedEof` error if the stream ends before `n`
+    /// bytes are available, in which case the available bytes remain unconsumed.
+    pub fn peek_exact(&mut self, n: usize) -> std::io::Result<&[u8]> {
+        let available = self.peek(n)?;
+        if available.len() < n {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof));
+        }
+
+        Ok(available)
+    }
+
+    /// Reads from the inner reader until at least `min_bytes` are buffered, or the end
+    /// of the stream is reached, without consuming them. Returns the number of bytes
+    /// now buffered, which may be more than `min_bytes`.
+    pub fn fill_to(&mut self, min_bytes: usize) -> std::io::Result<usize> {
+        self.peek(min_bytes)?;
+        Ok(self.peek_buffer.len())
+    }
+
+    /// Returns the next byte of the stream without consuming it, or `None` if
+    /// the end of the stream has been reached.
+    pub fn peek_byte(&mut self) -> std::io::Result<Option<u8>> {
+        Ok(self.peek(1)?.first().copied())
+    }
+
+    /// Returns the bytes that are buffered but have not been read yet, which includes
+    /// any bytes awaiting replay after a `reset` as well as bytes already read ahead
+    /// from the inner reader. This does not read from the inner reader or advance the
+    /// stream, but may rearrange the internal buffers so the bytes are contiguous.
+    pub fn buffered_bytes(&mut self) -> &[u8] {
+        self.move_replay_into_peek_buffer()
+            .expect("lookahead buffer is unbounded");
+        self.peek_buffer.as_slice()
+    }
+
+    /// Places bytes in front of everything else this reader has yet to return, so
+    /// they will be read next as if they came first from the inner reader. This is
+    /// intended to receive the buffered bytes of another reader (see `into_parts`)
+    /// when handing the inner reader over to this one.
+    pub fn absorb_from(&mut self, other_buffered: Vec<u8>) -> std::io::Result<()> {
+        self.move_replay_into_peek_buffer()?;
+        self.peek_buffer.prepend(&other_buffered)
+    }
+
+    /// Pushes bytes back onto the front of the stream so the next read returns them
+    /// first. The bytes do not need to be ones previously read, and are treated as
+    /// part of the stream from then on: `position` counts them when they are read, and
+    /// while marked they are replayed after a `reset` like any other marked read.
+    ///
+    /// Returns an `ErrorKind::OutOfMemory` error, leaving the stream unchanged, if the
+    /// bytes would exceed the limit of the mark buffer.
+    pub fn unread(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        if self.is_marked {
+            let used = self.mark_buffer.stored_len() + bytes.len();
+            if let Some(limit) = self.mark_buffer.limit().filter(|limit| used > *limit) {
+                return Err(MarkBufferError::LimitExceeded { used, limit }.into());
+            }
+        } else {
+            self.mark_buffer.purge_read();
+        }
+
+        self.mark_buffer.prepend(bytes)
+    }
+
+    /// Consumes up to `n` bytes without returning them, returning the number of bytes
+    /// skipped, which is fewer than `n` only if the end of the stream is reached.
+    /// While marked, skipped bytes are retained and will be replayed after a `reset`.
+    pub fn skip(&mut self, n: usize) -> std::io::Result<usize> {
+        let mut scratch = vec![0; n.min(DEFAULT_BUFFER_SIZE)];
+        let mut skipped = 0;
+        while skipped < n {
+            let length = (n - skipped).min(scratch.len());
+            let bytes_read = self.read_into_buf(&mut scratch[0..length])?;
+            if bytes_read == 0 {
+                break;
+            }
+
+            skipped += bytes_read;
+        }
+
+        Ok(skipped)
+    }
+
+    /// Reads bytes into `out` until the delimiter or the end of the stream is reached,
+    /// returning the number of bytes appended. The delimiter, if found, is included.
+    /// While marked, the bytes read are retained and will be replayed after a `reset`.
+    pub fn read_until(&mut self, delim: u8, out: &mut Vec<u8>) -> std::io::Result<usize> {
+        let mut single_byte_buf = [0; 1];
+        let mut bytes_read = 0;
+        while self.read_into_buf(&mut single_byte_buf)? > 0 {
+            out.push(single_byte_buf[0]);
+            bytes_read += 1;
+            if single_byte_buf[0] == delim {
+                break;
+            }
+        }
+
+        Ok(bytes_read)
+    }
+
+    /// Consumes a UTF-8 or UTF-16 byte-order mark at the current position, returning
+    /// which one was found. If the stream does not start with a byte-order mark, it
+    /// is left unchanged. Any outer marks are kept intact.
+    pub fn skip_bom(&mut self) -> std::io::Result<Option<Bom>> {
+        let id = self.push_mark();
+        let mut candidate = [0; 3];
+        let mut bytes_read = 0;
+        while bytes_read < candidate.len() {
+            match self.read_into_buf(&mut candidate[bytes_read..]) {
+                Ok(0) => break,
+                Ok(current_read) => bytes_read += current_read,
+                Err(e) => {
+                    self.pop_mark(id);
+                    return Err(e);
+                }
+            }
+        }
+
+        self.pop_mark(id);
+        let bom = Bom::detect(&candidate[0..bytes_read]);
+        if let Some(bom) = bom {
+            self.skip(bom.as_bytes().len())?;
+        }
+
+        Ok(bom)
+    }
+
+    /// Creates a reader that returns at most `limit` more bytes from this one, and
+    /// which can still be marked and reset. The limit counts the bytes consumed from
+    /// the stream, so bytes replayed after a `reset` are not counted twice.
+    pub fn take_markable(self, limit: u64) -> MarkableTake<R> {
+        MarkableTake {
+            end: self.position.saturating_add(limit),
+            reader: self,
+        }
+    }
+
+    /// Creates a reader that continues with `next` once this reader's stream ends. The
+    /// mark, along with everything buffered, carries over, and marks can span the seam
+    /// between the two streams: resetting into the exhausted first stream replays
+    /// from the mark buffer rather than the first reader.
+    pub fn chain_markable<R2>(self, next: R2) -> MarkableChain<R, R2>
+    where
+        R2: std::io::Read,
+    {
+        MarkableReader {
+            inner: self.inner.chain(next),
+            inner_complete: false,
+            is_marked: self.is_marked,
+            mark_buffer: self.mark_buffer,
+            peek_buffer: self.peek_buffer,
+            scratch: self.scratch,
+            inner_offset: self.inner_offset,
+            read_alignment: self.read_alignment,
+            recording: self.recording,
+            position: self.position,
+            nested_marks: self.nested_marks,
+            named_marks: self.named_marks,
+            total_discarded: self.total_discarded,
+            on_limit: self.on_limit,
+            sliding_window: self.sliding_window,
+            mark_invalidated: self.mark_invalidated,
+            sticky_mark: self.sticky_mark,
+            on_consume: self.on_consume,
+            consumed_to: self.consumed_to,
+            reclaim_after_replay: self.reclaim_after_replay,
+            mark_generation: self.mark_generation,
+        }
+    }
+
+    /// Converts this reader into a `BufferedMarkableReader` over the same inner reader
+    /// without losing any buffered state. The mark, including nested and named marks,
+    /// the bytes retained since it and any peeked bytes carry over, along with the
+    /// position and settings such as the mark limit, so a later `reset` on the buffered
+    /// reader replays exactly what it would have here.
+    pub fn into_buffered(self) -> BufferedMarkableReader<R> {
+        let state = ReaderSnapshot {
+            is_marked: self.is_marked,
+            mark_invalidated: self.mark_invalidated,
+            inner_complete: self.inner_complete,
+            mark_buffer: self.mark_buffer,
+            lookahead: self.peek_buffer,
+            position: self.position,
+            inner_offset: self.inner_offset,
+            nested_marks: self.nested_marks,
+            named_marks: self.named_marks,
+        };
+        let mut reader = BufferedMarkableReader::from_markable_state(
+            self.inner,
+            state,
+            self.total_discarded,
+            self.recording,
+            self.on_limit,
+            self.on_consume,
+            self.consumed_to,
+        );
+        reader.set_sliding_window(self.sliding_window);
+        reader.set_sticky_mark(self.sticky_mark);
+        match self.read_alignment {
+            Some(align) => reader.with_read_alignment(align),
+            None => reader,
+        }
+    }
+
+    /// Returns an iterator over the bytes of this reader that yields `None` at the end
+    /// of the stream. The reader can still be marked and reset through the iterator.
+    pub fn bytes_iter(&mut self) -> MarkableBytes<'_, R> {
+        MarkableBytes { reader: self }
+    }
+
+    /// Returns an iterator over the records of this reader separated by `delim`, such as
+    /// the lines of a log. Each record is read with `read_until` and yielded without its
+    /// delimiter. A final record without a trailing delimiter is still yielded.
+    pub fn records(&mut self, delim: u8) -> Records<'_, R> {
+        Records {
+            reader: self,
+            delim,
+        }
+    }
+
+    /// Returns to the last mark, replaying everything read since. This is an alias
+    /// of `reset` with the same behavior: if the stream is not marked it has no effect.
+    ///
+    /// Note that this differs from `std::io::Seek::rewind`, which seeks to the start of
+    /// the stream; to return to the start of a seekable stream use `rewind_to_start`.
+    pub fn rewind(&mut self) {
+        self.reset();
+    }
+
+    /// Returns to the last mark like `reset`, but stays marked and keeps the marked
+    /// bytes, so the region can be replayed again by another reset. This suits parsers
+    /// that make several passes over the same region. Nested marks are kept as well. If
+    /// the stream is not marked it has no effect.
+    pub fn reset_keep_mark(&mut self) {
+        if !self.is_marked || self.mark_invalidated {
+            return;
+        }
+
+        self.position -= self.mark_buffer.restart() as u64;
+    }
+
+    /// Marks the current position and reads the rest of the inner stream into the mark
+    /// buffer, leaving the reader at the mark so the whole remainder can be read again.
+    /// Each `reset_keep_mark` returns to the start for another pass, effectively making
+    /// any reader rewindable, while a plain `reset` allows one last pass and unmarks.
+    /// Any existing mark is replaced as by `mark`.
+    ///
+    /// Returns the number of bytes buffered. If they would exceed the mark buffer limit
+    /// an `ErrorKind::OutOfMemory` error is returned instead. Reads are sized to the
+    /// room left under the limit, so the reader stays marked with every byte that fit
+    /// and a `reset` still replays them from where buffering began.
+    pub fn buffer_all(&mut self) -> std::io::Result<usize> {
+        self.mark();
+        let mut chunk = [0; DEFAULT_MARKER_BUFFER_SIZE];
+        let mut buffered = 0;
+        loop {
+            // Once no room is left, single byte reads let the `on_limit` callback or
+            // the sliding window act before the limit is enforced
+            let chunk_len = self
+                .remaining_limit()
+                .unwrap_or(chunk.len())
+                .clamp(1, chunk.len());
+            match self.read_into_buf(&mut chunk[..chunk_len])? {
+                0 => break,
+                bytes_read => buffered += bytes_read,
+            }
+        }
+
+        self.reset_keep_mark();
+        Ok(buffered)
+    }
+
+    /// Returns to the last mark like `reset`, and once the replayed bytes have all been
+    /// read again, shrinks the mark buffer back to the capacity it started with, or
+    /// straight away if there is nothing to replay. This keeps a large marked region
+    /// from holding on to its memory for the life of the reader, at the cost of
+    /// growing the buffer again for the next large marked region. With sticky marks,
+    /// the buffer is shrunk only once the stream is unmarked and the replay is read.
+    pub fn reset_and_reclaim(&mut self) {
+        self.reset();
+        self.reclaim_after_replay = true;
+        self.reclaim_replayed();
+    }
+
+    /// Marks the stream like `mark`, returning a token that `reset_tagged` checks still
+    /// refers to this mark.
+    pub fn mark_tagged(&mut self) -> MarkToken {
+        self.mark();
+        MarkToken(self.mark_generation)
+    }
+
+    /// Resets the stream like `reset`, but only if `token` refers to the current mark.
+    ///
+    /// Returns an `ErrorKind::InvalidInput` error, leaving the stream untouched, if the
+    /// token is stale: the stream has been marked again, restored from a snapshot, or is
+    /// no longer marked since the token was returned by `mark_tagged`.
+    pub fn reset_tagged(&mut self, token: MarkToken) -> std::io::Result<()> {
+        if !self.is_marked || token.0 != self.mark_generation {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "mark token is stale",
+            ));
+        }
+
+        self.reset();
+        Ok(())
+    }
+
+    /// Moves back `n` bytes within the bytes read since the mark, so they will be read
+    /// again, while staying marked. If fewer than `n` bytes have been read since the
+    /// mark, this rewinds to the mark instead. Nested and named marks set after the new
+    /// position are discarded.
+    ///
+    /// Returns the number of bytes rewound, or an `ErrorKind::InvalidInput` error if the
+    /// stream is not marked.
+    pub fn rewind_by(&mut self, n: usize) -> std::io::Result<usize> {
+        if !self.is_marked {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "cannot rewind a stream that is not marked",
+            ));
+        }
+
+        let offset = self.mark_buffer.consumed().saturating_sub(n);
+        self.nested_marks
+            .retain(|nested_mark| nested_mark.offset <= offset);
+        self.named_marks
+            .retain(|_, mark_offset| *mark_offset <= offset);
+        let rewound = self.mark_buffer.rewind_to(offset);
+        self.position -= rewound as u64;
+        Ok(rewound)
+    }
+
+    /// Resets the stream to the previously marked position like `reset`, but fails with
+    /// `ErrorKind::InvalidData` if the sliding window has dropped bytes since the mark,
+    /// as the region can no longer be replayed. The stream is unmarked either way.
+    pub fn checked_reset(&mut self) -> std::io::Result<()> {
+        let invalidated = self.is_marked && self.mark_invalidated;
+        self.reset();
+        if invalidated {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "the mark was invalidated by the sliding window",
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Resets the stream to the previously marked position, if it is set.
+    ///
+    /// Returns `true` if the stream was marked and has been reset, or `false`,
+    /// leaving the stream untouched, if it was not marked.
+    pub fn try_reset(&mut self) -> bool {
+        if !self.is_marked {
+            return false;
+        }
+
+        self.reset();
+        true
+    }
+
+    /// Returns the bytes read since the stream was marked and unmarks the stream,
+    /// leaving it at its current position. This commits the marked reads like
+    /// `commit_mark` does, but also hands back the data, which is useful for keeping
+    /// the original text of a speculatively parsed region.
+    ///
+    /// Returns an empty vec if the stream is not marked.
+    pub fn take_marked(&mut self) -> Vec<u8> {
+        if !self.is_marked {
+            return Vec::new();
+        }
+
+        self.is_marked = false;
+        self.nested_marks.clear();
+        self.named_marks.clear();
+        self.mark_buffer.take_read()
+    }
+
+    /// Moves the mark to the current position, dropping the bytes read since the mark
+    /// while keeping any replayed bytes that have not been read yet. The stream is
+    /// marked if it was not already. This is equivalent to calling `mark`, which also
+    /// only discards bytes that have already been read, and is provided for
+    /// sliding-window scanners where the intent reads more clearly.
+    ///
+    /// Returns the number of bytes that were discarded.
+    pub fn advance_mark(&mut self) -> usize {
+        self.mark()
+    }
+
+    /// Marks the current position and returns a guard that resets the stream to it when
+    /// dropped, unless `commit` is called on the guard first. The guard derefs to the
+    /// reader, so reads can be made through it while it is held. Like `push_mark`, any
+    /// outer marks are kept intact.
+    pub fn mark_guard(&mut self) -> MarkGuard<'_, R> {
+        let id = self.push_mark();
+        MarkGuard {
+            reader: self,
+            id,
+            committed: false,
+        }
+    }
+
+    /// Runs `f` as a transaction over the stream. The current position is marked before
+    /// `f` is called; if `f` returns `Ok` its reads are kept, otherwise the stream is
+    /// reset to where it was so the next read sees the same bytes again. Like
+    /// `push_mark`, outer marks are kept intact, so transactions can be nested.
+    pub fn transaction<T, E>(&mut self, f: impl FnOnce(&mut Self) -> Result<T, E>) -> Result<T, E> {
+        let id = self.push_mark();
+        let result = f(self);
+        match result {
+            Ok(_) => self.commit_mark(id),
+            Err(_) => self.pop_mark(id),
+        }
+
+        result
+    }
+
+    /// Takes a snapshot of the buffered bytes, marks and position of this reader, to be
+    /// rolled back to later with `restore`. Unlike a mark, any number of snapshots can be
+    /// held at once. The inner reader is not captured, see `ReaderSnapshot`.
+    pub fn snapshot(&self) -> ReaderSnapshot {
+        ReaderSnapshot {
+            is_marked: self.is_marked,
+            mark_invalidated: self.mark_invalidated,
+            inner_complete: self.inner_complete,
+            mark_buffer: self.mark_buffer.clone(),
+            lookahead: self.peek_buffer.clone(),
+            position: self.position,
+            inner_offset: self.inner_offset,
+            nested_marks: self.nested_marks.clone(),
+            named_marks: self.named_marks.clone(),
+        }
+    }
+
+    /// Rolls this reader back to a snapshot taken by `snapshot`. The inner reader must
+    /// already be back at `snapshot.inner_offset()`, e.g., by seeking it through
+    /// `get_mut`, unless nothing has been read from it since the snapshot was taken.
+    /// Settings such as the mark limit are kept as they are.
+    pub fn restore(&mut self, snapshot: ReaderSnapshot) {
+        self.is_marked = snapshot.is_marked;
+        self.mark_invalidated = snapshot.mark_invalidated;
+        self.inner_complete = snapshot.inner_complete;
+        self.mark_buffer = snapshot.mark_buffer;
+        self.peek_buffer = snapshot.lookahead;
+        self.position = snapshot.position;
+        self.inner_offset = snapshot.inner_offset;
+        self.nested_marks = snapshot.nested_marks;
+        self.named_marks = snapshot.named_marks;
+        self.mark_generation += 1;
+    }
+
+    /// Marks the current position as a nested backtracking point, marking the stream
+    /// if it is not already marked. Outer marks, including one set by `mark`, are
+    /// kept intact and continue to buffer reads.
+    ///
+    /// Returns an id to pass to `pop_mark` or `commit_mark`.
+    pub fn push_mark(&mut self) -> MarkId {
+        let starts_marking = !self.is_marked;
+        if starts_marking {
+            self.mark();
+        }
+
+        self.nested_marks.push(NestedMark {
+            offset: self.mark_buffer.consumed(),
+            starts_marking,
+        });
+        MarkId(self.nested_marks.len() - 1)
+    }
+
+    /// Resets the stream to the position of a nested mark, discarding it and any marks
+    /// pushed after it. Outer marks remain, so the stream stays marked unless this was
+    /// the mark that started marking. Ids of marks that have already been discarded
+    /// are ignored.
+    pub fn pop_mark(&mut self, id: MarkId) {
+        if id.0 >= self.nested_marks.len() {
+            return;
+        }
+
+        let nested_mark = self.nested_marks[id.0];
+        if nested_mark.starts_marking {
+            // This mark started marking, so a sticky mark must not keep the stream marked
+            let sticky_mark = std::mem::take(&mut self.sticky_mark);
+            self.reset();
+            self.sticky_mark = sticky_mark;
+        } else {
+            self.nested_marks.truncate(id.0);
+            self.named_marks
+                .retain(|_, mark_offset| *mark_offset <= nested_mark.offset);
+            self.position -= self.mark_buffer.rewind_to(nested_mark.offset) as u64;
+        }
+    }
+
+    /// Discards a nested mark, and any marks pushed after it, without moving the stream.
+    /// Outer marks remain, so the stream stays marked unless this was the mark that
+    /// started marking. Ids of marks that have already been discarded are ignored.
+    pub fn commit_mark(&mut self, id: MarkId) {
+        if id.0 >= self.nested_marks.len() {
+            return;
+        }
+
+        let nested_mark = self.nested_marks[id.0];
+        self.nested_marks.truncate(id.0);
+        if nested_mark.starts_marking {
+            self.is_marked = false;
+            self.named_marks.clear();
+            self.total_discarded += self.mark_buffer.purge_read() as u64;
+        }
+    }
+
+    /// Marks the current position under the provided name, marking the stream if it is
+    /// not already marked. If the name is already in use, it is moved to this position.
+    pub fn mark_named(&mut self, name: &str) {
+        if !self.is_marked {
+            self.mark();
+        }
+
+        self.named_marks
+            .insert(name.to_string(), self.mark_buffer.consumed());
+    }
+
+    /// Resets the stream to the position of the named mark. The stream stays marked and
+    /// the named mark remains available, but any marks set after it are discarded.
+    ///
+    /// Returns `false`, leaving the stream untouched, if there is no mark with this name.
+    pub fn reset_named(&mut self, name: &str) -> bool {
+        let Some(offset) = self.named_marks.get(name).copied() else {
+            return false;
+        };
+
+        self.named_marks
+            .retain(|_, mark_offset| *mark_offset <= offset);
+        self.nested_marks
+            .retain(|nested_mark| nested_mark.offset <= offset);
+        self.position -= self.mark_buffer.rewind_to(offset) as u64;
+        true
+    }
+
+    /// Reads at most `buf.len()` bytes from the underlying buffers to fill the provided buffer.
+    /// Returns `Ok(0)` at the end of the stream.
+    fn read_into_buf(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+
+        // If marked, then we only read from the read buffer and all
+        // read bytes go in the mark buffer.
+        // If not marked, we read what we can from the mark buffer and then read the remaining
+        // bytes from the underlying reader.
+        if self.is_marked {
+            // First grab what we can from the mark buffer
+            let buffer_bytes_read = self.mark_buffer.read_into(buf, 0);
+            // Then fill and retain remaining from the inner reader
+            let inner_bytes_read =
+                self.read_data_into_buf_and_marked_stream(buf, buffer_bytes_read)?;
+            let bytes_read = inner_bytes_read + buffer_bytes_read;
+            self.advance_position(&buf[..bytes_read]);
+            Ok(bytes_read)
+        } else {
+            // Otherwise, read what we can from the mark buffer and then go to inner reader
+            // for any remaining bytes
+            let mut bytes_read = self.mark_buffer.read_into(buf, 0);
+            bytes_read += match self.fill_from_inner(buf, bytes_read) {
+                Ok(inner_bytes_read) => inner_bytes_read,
+                Err(_) if bytes_read > 0 => 0,
+                Err(e) => return Err(e),
+            };
+
+            self.advance_position(&buf[..bytes_read]);
+            Ok(bytes_read)
+        }
+    }
+
+    /// Invokes the `on_limit` callback, if any, when holding `used` marked bytes would
+    /// exceed the limit, raising the limit if it returns a new one
+    fn consult_on_limit(&mut self, used: usize) -> std::io::Result<()> {
+        let limit = self.mark_buffer.limit().filter(|limit| used > *limit);
+        if let (Some(on_limit), Some(limit)) = (&self.on_limit, limit) {
+            let mut on_limit = on_limit.lock().unwrap_or_else(PoisonError::into_inner);
+            if let Some(new_limit) = on_limit(used, limit) {
+                self.mark_buffer.set_limit(Some(new_limit))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Shrinks the mark buffer for `reset_and_reclaim` once the replayed bytes have
+    /// all been read and the stream is no longer marked
+    fn reclaim_replayed(&mut self) {
+        if self.reclaim_after_replay && !self.is_marked && self.mark_buffer.len() == 0 {
+            self.reclaim_after_replay = false;
+            self.mark_buffer.purge_read();
+            self.mark_buffer.shrink_to_initial();
+        }
+    }
+
+    /// Advances the position past bytes that were just delivered, passing those not
+    /// delivered before to the `on_consume` callback and finishing a pending reclaim
+    fn advance_position(&mut self, delivered: &[u8]) {
+        self.reclaim_replayed();
+        Self::advance_past(
+            &mut self.position,
+            &mut self.consumed_to,
+            &self.on_consume,
+            delivered,
+        );
+    }
+
+    /// Advances `position` past `delivered`, passing the bytes beyond `consumed_to` to
+    /// the `on_consume` callback. This borrows only the fields it needs, so the bytes
+    /// can still be borrowed from one of the buffers.
+    fn advance_past(
+        position: &mut u64,
+        consumed_to: &mut u64,
+        on_consume: &Option<ConsumeHook>,
+        delivered: &[u8],
+    ) {
+        let start = *position;
+        *position += delivered.len() as u64;
+        if *position <= *consumed_to {
+            return;
+        }
+
+        if let Some(on_consume) = on_consume {
+            let first_new = consumed_to.saturating_sub(start) as usize;
+            let mut on_consume = on_consume.lock().unwrap_or_else(PoisonError::into_inner);
+            on_consume(&delivered[first_new..]);
+        }
+        *consumed_to = *position;
+    }
+
+    /// Skips up to `n` buffered bytes that need not be retained for `consume`, which
+    /// are those awaiting replay and, while unmarked, those in the lookahead buffer.
+    ///
+    /// Returns the number of bytes skipped.
+    fn skip_unretained(&mut self, n: usize) -> usize {
+        let mut skipped = 0;
+        while skipped < n {
+            let buffer = if self.mark_buffer.len() > 0 {
+                &mut self.mark_buffer
+            } else if !self.is_marked {
+                &mut self.peek_buffer
+            } else {
+                break;
+            };
+
+            let bytes = buffer.unread_front(n - skipped);
+            if bytes.is_empty() {
+                break;
+            }
+
+            let len = bytes.len();
+            Self::advance_past(
+                &mut self.position,
+                &mut self.consumed_to,
+                &self.on_consume,
+                bytes,
+            );
+            buffer.skip(len);
+            skipped += len;
+        }
+
+        self.reclaim_replayed();
+        skipped
+    }
+
+    /// Retains bytes that were read while marked, giving the `on_limit` callback a
+    /// chance to raise the limit first if they would exceed it
+    fn retain_marked(&mut self, bytes: &[u8]) -> std::io::Result<()> {
+        self.consult_on_limit(self.mark_buffer.stored_len() + bytes.len())?;
+
+        if self.sliding_window {
+            let used = self.mark_buffer.stored_len() + bytes.len();
+            if let Some(limit) = self.mark_buffer.limit().filter(|limit| used > *limit) {
+                let skipped = self.slide_window(used - limit).min(bytes.len());
+                return self.mark_buffer.record(&bytes[skipped..]);
+            }
+        }
+
+        self.mark_buffer.record(bytes)
+    }
+
+    /// Drops the oldest `n` marked bytes to make room in the mark buffer, starting with
+    /// those already retained and continuing into the bytes about to be retained,
+    /// invalidating the mark and any nested or named marks that pointed into them.
+    ///
+    /// Returns the number of bytes to skip from the front of those about to be retained.
+    fn slide_window(&mut self, n: usize) -> usize {
+        let dropped = self.mark_buffer.discard_oldest(n);
+        self.mark_invalidated = true;
+        self.total_discarded += n as u64;
+        if self
+            .nested_marks
+            .iter()
+            .any(|nested_mark| nested_mark.offset < n)
+        {
+            self.nested_marks.clear();
+        }
+        for nested_mark in &mut self.nested_marks {
+            nested_mark.offset -= n;
+        }
+        self.named_marks.retain(|_, mark_offset| *mark_offset >= n);
+        for mark_offset in self.named_marks.values_mut() {
+            *mark_offset -= n;
+        }
+
+        n - dropped
+    }
+
+    /// Moves bytes awaiting replay to the front of the peek buffer so that all unread
+    /// buffered bytes are contiguous. They will be recorded back into the mark buffer
+    /// when read while marked.
+    fn move_replay_into_peek_buffer(&mut self) -> std::io::Result<()> {
+        let pending = self.mark_buffer.split_unread();
+        self.peek_buffer.prepend(&pending)
+    }
+
+    /// Fills the provided buffer with bytes from the underlying stream and also places those
+    /// bytes into the mark buffer. Bytes that cannot be retained are put back in the peek
+    /// buffer, so a failed read never loses any. Errors are only returned when there are no
+    /// bytes before the offset, which the caller has already taken from the mark buffer.
+    fn read_data_into_buf_and_marked_stream(
+        &mut self,
+        buf: &mut [u8],
+        offset: usize,
+    ) -> std::io::Result<usize> {
+        let inner_bytes_read = match self.fill_from_inner(buf, offset) {
+            Ok(inner_bytes_read) => inner_bytes_read,
+            Err(_) if offset > 0 => return Ok(0),
+            Err(e) => return Err(e),
+        };
+        if inner_bytes_read > 0 {
+            // The inner bytes read will be the n bytes following the offset. These have
+            // already been delivered, so they are retained behind the read position
+            let inner_bytes = &buf[offset..offset + inner_bytes_read];
+            if let Err(e) = self.retain_marked(inner_bytes) {
+                self.peek_buffer.prepend(inner_bytes)?;
+                return if offset > 0 { Ok(0) } else { Err(e) };
+            }
+        }
+
+        Ok(inner_bytes_read)
+    }
+
+    /// Fills the provided buffer with peeked bytes and then bytes from the inner reader
+    /// starting with at the provided offset. If the inner reader fails after some bytes
+    /// were read, those bytes are returned and the next read tries the inner reader again.
+    fn fill_from_inner(&mut self, buf: &mut [u8], offset: usize) -> std::io::Result<usize> {
+        let mut read = self.peek_buffer.read_into(buf, offset);
+        if self.inner_complete {
+            return Ok(read);
+        }
+
+        if self.read_alignment.is_some() {
+            while read + offset < buf.len() && !self.inner_complete {
+                match self.fill_peek_buffer(buf.len() - offset - read) {
+                    Ok(_) => {}
+                    Err(_) if read > 0 => break,
+                    Err(e) => return Err(e),
+                }
+                read += self.peek_buffer.read_into(buf, offset + read);
+            }
+            return Ok(read);
+        }
+
+        let mut single_byte_buf = [0; 1];
+        while read + offset < buf.len() {
+            let current_read = match self.read_inner(&mut single_byte_buf) {
+                Ok(current_read) => current_read,
+                Err(_) if read > 0 => break,
+                Err(e) => return Err(e),
+            };
+            if current_read > 0 {
+                buf[read + offset] = single_byte_buf[0];
+                read += 1;
+            } else {
+                self.inner_complete = true;
+                break;
+            }
+        }
+
+        Ok(read)
+    }
+
+    /// Reads up to `length` bytes from the inner reader into the peek buffer, though
+    /// no more than `DEFAULT_BUFFER_SIZE` at a time so a large request does not
+    /// allocate before the stream is known to hold the bytes. If a read alignment is
+    /// set, the read is extended to end on the next alignment boundary and short reads
+    /// are continued so the following read begins aligned. If the inner reader fails
+    /// partway, the bytes already read are kept and the next read tries it again.
+    fn fill_peek_buffer(&mut self, length: usize) -> std::io::Result<usize> {
+        let length = length.min(DEFAULT_BUFFER_SIZE);
+        let read_length = match self.read_alignment {
+            Some(align) => {
+                let misalignment = (self.inner_offset % align as u64) as usize;
+                (misalignment + length).div_ceil(align) * align - misalignment
+            }
+            None => length,
+        };
+
+        // the scratch buffer is kept between refills so that steady state peeks
+        // don't allocate; it is taken so the inner reader can be borrowed mutably
+        let mut scratch = std::mem::take(&mut self.scratch);
+        scratch.resize(read_length, 0);
+        let result = self.fill_scratch(&mut scratch).and_then(|filled| {
+            self.peek_buffer.write_all(&scratch[0..filled])?;
+            Ok(filled)
+        });
+        self.scratch = scratch;
+        result
+    }
+
+    /// Reads from the inner reader into the scratch buffer, stopping after the first
+    /// read unless a read alignment is set, in which case it is filled completely or
+    /// until the end of the stream
+    fn fill_scratch(&mut self, scratch: &mut [u8]) -> std::io::Result<usize> {
+        let mut filled = 0;
+        while filled < scratch.len() {
+            let bytes_read = match self.read_inner(&mut scratch[filled..]) {
+                Ok(bytes_read) => bytes_read,
+                Err(_) if filled > 0 => break,
+                Err(e) => return Err(e),
+            };
+            if bytes_read == 0 {
+                self.inner_complete = true;
+                break;
+            }
+
+            filled += bytes_read;
+            if self.read_alignment.is_none() {
+                break;
+            }
+        }
+
+        Ok(filled)
+    }
+
+    /// Shortens a read of `length` bytes made straight from the inner reader so it ends
+    /// on an alignment boundary, if a read alignment is set. Returns 0 if `length` does
+    /// not reach the next boundary.
+    fn direct_read_len(&self, length: usize) -> usize {
+        match self.read_alignment {
+            Some(align) => aligned_len(self.inner_offset, align, length),
+            None => length,
+        }
+    }
+
+    /// Reads from the inner reader, tracking the offset of the inner reader and
+    /// appending the bytes read to the recording, if enabled. Reads that are
+    /// interrupted are retried. A limited recording caps the read at the room it has
+    /// left, and once full fails the read before anything is taken from the inner
+    /// reader, so no bytes are read without being recorded.
+    fn read_inner(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let buf = match self
+            .recording
+            .as_ref()
+            .and_then(|recording| recording.limit().map(|limit| (recording.len(), limit)))
+        {
+            Some((used, limit)) if used >= limit && !buf.is_empty() => {
+                return Err(MarkBufferError::LimitExceeded {
+                    used: used + buf.len(),
+                    limit,
+                }
+                .into());
+            }
+            Some((used, limit)) => {
+                let room = (limit - used).min(buf.len());
+                &mut buf[..room]
+            }
+            None => buf,
+        };
+        let bytes_read = loop {
+            match self.inner.read(buf) {
+                Ok(bytes_read) => break bytes_read,
+                Err(e) if e.kind() == std::io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(e),
+            }
+        };
+        self.inner_offset += bytes_read as u64;
+        if let Some(recording) = self.recording.as_mut() {
+            recording.write_all(&buf[0..bytes_read])?;
+        }
+
+        Ok(bytes_read)
+    }
+}
+
+impl<R> MarkerStream for MarkableReader<R> {
+    /// Marks the location of the inner stream. From this point forward
+    /// reads will be cached. If the stream was marked prior to this call
+    /// the bytes read since that mark are discarded, while bytes that were
+    /// replayed by a `reset` but not yet read again are kept and will still be
+    /// read next. Any nested or named marks are discarded.
+    ///
+    /// Returns the number of bytes that were discarded, which are only bytes that
+    /// have already been read.
+    fn mark(&mut self) -> usize {
+        self.is_marked = true;
+        self.mark_invalidated = false;
+        self.mark_generation += 1;
+        self.nested_marks.clear();
+        self.named_marks.clear();
+        let discarded = self.mark_buffer.purge_read();
+        self.total_discarded += discarded as u64;
+        discarded
+    }
+
+    /// Resets the stream previously marked position, if it is set.
+    /// If the reader was not previously marked, this has no affect.
+    ///
+    fn reset(&mut self) {
+        if !self.is_marked {
+            return;
+        }
+
+        if self.sticky_mark && !self.mark_invalidated {
+            // Sticky marks return to the mark like `reset_keep_mark`
+            self.position -= self.mark_buffer.restart() as u64;
+            return;
+        }
+
+        self.is_marked = false;
+        self.nested_marks.clear();
+        self.named_marks.clear();
+        if std::mem::take(&mut self.mark_invalidated) {
+            return;
+        }
+
+        self.position -= self.mark_buffer.restart() as u64;
+    }
+
+    fn clear_buffer(&mut self) {
+        self.is_marked = false;
+        self.nested_marks.clear();
+        self.named_marks.clear();
+        self.total_discarded += self.mark_buffer.stored_len() as u64;
+        self.mark_buffer.clear();
+    }
+
+    fn is_marked(&self) -> bool {
+        self.is_marked
+    }
+}
+
+impl<R> MarkableReader<R>
+where
+    R: SeekableInner,
+{
+    /// Seeks the inner reader back to the start of the stream, dropping the mark and
+    /// all buffered bytes, so the whole stream will be read again. Unlike `rewind`,
+    /// this does not depend on a mark having been set.
+    pub fn rewind_to_start(&mut self) -> std::io::Result<()> {
+        std::io::Seek::seek(self, std::io::SeekFrom::Start(0))?;
+        Ok(())
+    }
+}
+
+impl<R> std::fmt::Debug for MarkableReader<R>
+where
+    R: std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("MarkableReader")
+            .field("inner", &self.inner)
+            .field("is_marked", &self.is_marked)
+            .field("mark_buffer_len", &self.mark_buffer.stored_len())
+            .field("mark_limit", &self.mark_buffer.limit())
+            .field("peek_buffer_len", &self.peek_buffer.len())
+            .field("position", &self.position)
+            .finish()
+    }
+}
+
+impl<R> std::io::Read for MarkableReader<R>
+where
+    R: std::io::Read,
+{
+    /// If an error is returned, no bytes were consumed, so everything retained while
+    /// marked is exactly what was delivered and a later `reset` replays just those
+    /// bytes. When the inner reader fails after some bytes were read, or a marked read
+    /// would exceed the limit after replayed bytes were read, the bytes read so far are
+    /// returned instead of the error. The next read tries the inner reader again, so an
+    /// error that persists is reported then.
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        match self.read_into_buf(buf)? {
+            0 if !self.is_marked && !buf.is_empty() => {
+                Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof))
+            }
+            bytes_read => Ok(bytes_read),
+        }
+    }
+
+    /// Fills each slice in turn, stopping early only if a slice cannot be filled
+    /// completely. While marked, the bytes read into every slice are retained.
+    fn read_vectored(&mut self, bufs: &mut [std::io::IoSliceMut<'_>]) -> std::io::Result<usize> {
+        let mut total_read = 0;
+        for buf in bufs.iter_mut().filter(|buf| !buf.is_empty()) {
+            let bytes_read = match self.read_into_buf(buf) {
+                Ok(bytes_read) => bytes_read,
+                Err(_) if total_read > 0 => break,
+                Err(e) => return Err(e),
+            };
+
+            total_read += bytes_read;
+            if bytes_read < buf.len() {
+                break;
+            }
+        }
+
+        match total_read {
+            0 if !self.is_marked && bufs.iter().any(|buf| !buf.is_empty()) => {
+                Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof))
+            }
+            bytes_read => Ok(bytes_read),
+        }
+    }
+}
+
+/// Forwards writes to the inner stream, which allows bidirectional transports
+/// (e.g., a `TcpStream`) to be written to while wrapped. Writing does not
+/// interact with the mark or peek buffers, so bytes already buffered for reading
+/// are unaffected.
+impl<R> std::io::Write for MarkableReader<R>
+where
+    R: std::io::Write,
+{
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.inner.write(buf)
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+impl<R> std::io::Seek for MarkableReader<R>
+where
+    R: SeekableInner,
+{
+    /// Seeks within the stream. A `SeekFrom::Current` seek that moves backward by no
+    /// more than the bytes already read from the mark buffer is satisfied from the
+    /// buffer without touching the inner reader, and keeps the stream marked.
+    ///
+    /// All other seeks, including any `SeekFrom::Start` or `SeekFrom::End` seek, are
+    /// delegated to the inner reader and clear the mark along with all buffered bytes.
+    ///
+    /// Positions are those of the inner reader. Bytes given by `with_prefix` or
+    /// `unread` are counted as if they were read from the inner reader just before its
+    /// current position, so a `SeekFrom::Current` seek returns an
+    /// `ErrorKind::InvalidInput` error, leaving the stream untouched, while more of
+    /// them are buffered than the inner reader has read.
+    fn seek(&mut self, pos: std::io::SeekFrom) -> std::io::Result<u64> {
+        let pos = match pos {
+            std::io::SeekFrom::Current(delta) => {
+                // The inner reader is ahead of this reader by the bytes that are buffered
+                let buffered = (self.mark_buffer.len() + self.peek_buffer.len()) as u64;
+                let current = self
+                    .inner
+                    .stream_position()?
+                    .checked_sub(buffered)
+                    .ok_or_else(seek_before_inner_start)?;
+                let rewind = delta.unsigned_abs() as usize;
+                if delta <= 0 && rewind <= self.mark_buffer.consumed() {
+                    let target = current
+                        .checked_sub(rewind as u64)
+                        .ok_or_else(seek_before_inner_start)?;
+                    let offset = self.mark_buffer.consumed() - rewind;
+                    self.nested_marks
+                        .retain(|nested_mark| nested_mark.offset <= offset);
+                    self.named_marks
+                        .retain(|_, mark_offset| *mark_offset <= offset);
+                    self.position -= self.mark_buffer.rewind_to(offset) as u64;
+                    return Ok(target);
+                }
+
+                let target = current
+                    .checked_add_signed(delta)
+                    .ok_or_else(seek_before_inner_start)?;
+                std::io::SeekFrom::Start(target)
+            }
+            pos => pos,
+        };
+        let new_position = self.inner.seek(pos)?;
+        self.clear_buffer();
+        self.peek_buffer.clear();
+        self.inner_complete = false;
+        self.inner_offset = new_position;
+        self.position = new_position;
+        Ok(new_position)
+    }
+}
+
+/// An iterator over the delimited records of a `MarkableReader`, created by `records`
+pub struct Records<'a, R> {
+    reader: &'a mut MarkableReader<R>,
+    delim: u8,
+}
+
+impl<R> Iterator for Records<'_, R>
+where
+    R: std::io::Read,
+{
+    type Item = std::io::Result<Vec<u8>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut record = Vec::new();
+        match self.reader.read_until(self.delim, &mut record) {
+            Ok(0) => None,
+            Ok(_) => {
+                if record.last() == Some(&self.delim) {
+                    record.pop();
+                }
+                Some(Ok(record))
+            }
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+/// An iterator over the bytes of a `MarkableReader`, created by `bytes_iter`.
+/// The reader can be marked and reset through the iterator.
+pub struct MarkableBytes<'a, R> {
+    reader: &'a mut MarkableReader<R>,
+}
+
+impl<R> Iterator for MarkableBytes<'_, R>
+where
+    R: std::io::Read,
+{
+    type Item = std::io::Result<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut single_byte_buf = [0; 1];
+        match self.reader.read_into_buf(&mut single_byte_buf) {
+            Ok(0) => None,
+            Ok(_) => Some(Ok(single_byte_buf[0])),
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+impl<R> MarkerStream for MarkableBytes<'_, R> {
+    fn mark(&mut self) -> usize {
+        self.reader.mark()
+    }
+
+    fn reset(&mut self) {
+        self.reader.reset()
+    }
+
+    fn clear_buffer(&mut self) {
+        self.reader.clear_buffer()
+    }
+
+    fn is_marked(&self) -> bool {
+        self.reader.is_marked
+    }
+}
+
+/// An iterator over the bytes of an owned `MarkableReader`, created by `into_iter`.
+/// The reader can be marked and reset through the iterator.
+pub struct MarkableIntoBytes<R> {
+    reader: MarkableReader<R>,
+}
+
+impl<R> MarkableIntoBytes<R> {
+    /// Unwraps the iterator, returning the reader at its current position
+    pub fn into_inner(self) -> MarkableReader<R> {
+        self.reader
+    }
+}
+
+impl<R> Iterator for MarkableIntoBytes<R>
+where
+    R: std::io::Read,
+{
+    type Item = std::io::Result<u8>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut single_byte_buf = [0; 1];
+        match self.reader.read_into_buf(&mut single_byte_buf) {
+            Ok(0) => None,
+            Ok(_) => Some(Ok(single_byte_buf[0])),
+            Err(e) => Some(Err(e)),
+        }
+    }
+}
+
+impl<R> MarkerStream for MarkableIntoBytes<R> {
+    fn mark(&mut self) -> usize {
+        self.reader.mark()
+    }
+
+    fn reset(&mut self) {
+        self.reader.reset()
+    }
+
+    fn clear_buffer(&mut self) {
+        self.reader.clear_buffer()
+    }
+
+    fn is_marked(&self) -> bool {
+        self.reader.is_marked
+    }
+}
+
+impl<R> IntoIterator for MarkableReader<R>
+where
+    R: std::io::Read,
+{
+    type Item = std::io::Result<u8>;
+    type IntoIter = MarkableIntoBytes<R>;
+
+    /// Consumes the reader, returning an iterator over the rest of its bytes that ends
+    /// at the end of the stream
+    fn into_iter(self) -> Self::IntoIter {
+        MarkableIntoBytes { reader: self }
+    }
+}
+
+/// A scoped mark created by `mark_guard`. Dropping the guard resets the reader to the
+/// position it was created at, unless `commit` was called.
+pub struct MarkGuard<'a, R>
+where
+    R: std::io::Read,
+{
+    reader: &'a mut MarkableReader<R>,
+    id: MarkId,
+    committed: bool,
+}
+
+impl<R> MarkGuard<'_, R>
+where
+    R: std::io::Read,
+{
+    /// Keeps everything read through the guard, leaving the reader at its current position
+    pub fn commit(mut self) {
+        self.reader.commit_mark(self.id);
+        self.committed = true;
+    }
+}
+
+impl<R> std::ops::Deref for MarkGuard<'_, R>
+where
+    R: std::io::Read,
+{
+    type Target = MarkableReader<R>;
+
+    fn deref(&self) -> &Self::Target {
+        self.reader
+    }
+}
+
+impl<R> std::ops::DerefMut for MarkGuard<'_, R>
+where
+    R: std::io::Read,
+{
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        self.reader
+    }
+}
+
+impl<R> std::io::Read for MarkGuard<'_, R>
+where
+    R: std::io::Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        self.reader.read(buf)
+    }
+}
+
+impl<R> Drop for MarkGuard<'_, R>
+where
+    R: std::io::Read,
+{
+    fn drop(&mut self) {
+        if !self.committed {
+            self.reader.pop_mark(self.id);
+        }
+    }
+}
+
+/// A `MarkableReader` over two streams read one after the other, created by
+/// `chain_markable`
+pub type MarkableChain<R, R2> = MarkableReader<std::io::Chain<R, R2>>;
+
+/// A reader that limits the bytes read from a `MarkableReader`, created by
+/// `take_markable`. Unlike `std::io::Take`, it can still be marked and reset.
+pub struct MarkableTake<R> {
+    reader: MarkableReader<R>,
+    end: u64,
+}
+
+impl<R> MarkableTake<R>
+where
+    R: std::io::Read,
+{
+    /// Returns the number of bytes that can still be read before the limit is reached
+    pub fn limit(&self) -> u64 {
+        self.end.saturating_sub(self.reader.position)
+    }
+
+    /// Gets a reference to the limited reader
+    pub fn get_ref(&self) -> &MarkableReader<R> {
+        &self.reader
+    }
+
+    /// Gets a mutable reference to the limited reader. Bytes read directly from it
+    /// still count towards the limit.
+    pub fn get_mut(&mut self) -> &mut MarkableReader<R> {
+        &mut self.reader
+    }
+
+    /// Returns the limited reader
+    pub fn into_inner(self) -> MarkableReader<R> {
+        self.reader
+    }
+}
+
+impl<R> std::io::Read for MarkableTake<R>
+where
+    R: std::io::Read,
+{
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let length = (buf.len() as u64).min(self.limit()) as usize;
+        self.reader.read_into_buf(&mut buf[0..length])
+    }
+}
+
+impl<R> MarkerStream for MarkableTake<R> {
+    fn mark(&mut self) -> usize {
+        self.reader.mark()
+    }
+
+    fn reset(&mut self) {
+        self.reader.reset()
+    }
+
+    fn clear_buffer(&mut self) {
+        self.reader.clear_buffer()
+    }
+
+    fn is_marked(&self) -> bool {
+        self.reader.is_marked
+    }
+}
+
+impl<R> From<R> for MarkableReader<R>
+where
+    R: std::io::Read,
+{
+    fn from(value: R) -> Self {
+        MarkableReader::new(value)
+    }
+}
+
+/// Configures and creates a `MarkableReader`, covering combinations the
+/// constructors do not, such as an unbounded mark buffer with a custom
+/// initial capacity.
+///
+/// # Example
+// ```
+// let file = std::fs::File::open("path.bin").unwrap();
+// let mut reader = MarkableReaderBuilder::new()
+//     .mark_capacity(4 * 1024)
+//     .mark_limit(64 * 1024)
+//     .build(file);
+// ```
+#[derive(Debug, Clone)]
+pub struct MarkableReaderBuilder {
+    mark_capacity: usize,
+    mark_limit: Option<usize>,
+    sticky_mark: bool,
+}
+
+impl MarkableReaderBuilder {
+    /// Creates a builder with the same defaults as `MarkableReader::new`
+    pub fn new() -> MarkableReaderBuilder {
+        MarkableReaderBuilder {
+            mark_capacity: DEFAULT_MARKER_BUFFER_SIZE,
+            mark_limit: None,
+            sticky_mark: false,
+        }
+    }
+
+    /// Sets the initial capacity of the mark buffer
+    pub fn mark_capacity(mut self, capacity: usize) -> MarkableReaderBuilder {
+        self.mark_capacity = capacity;
+        self
+    }
+
+    /// Sets the limit of the mark buffer. Any reads that exceed the limit
+    /// will result in an `std::io::Error(ErrorKind::OutOfMemory)` error
+    pub fn mark_limit(mut self, limit: usize) -> MarkableReaderBuilder {
+        self.mark_limit = Some(limit);
+        self
+    }
+
+    /// Enables sticky marks, so `reset` keeps the stream marked. See
+    /// `MarkableReader::set_sticky_mark`
+    pub fn sticky_mark(mut self, enabled: bool) -> MarkableReaderBuilder {
+        self.sticky_mark = enabled;
+        self
+    }
+
+    /// Creates a reader over `inner` using the configured settings
+    pub fn build<R>(self, inner: R) -> MarkableReader<R>
+    where
+        R: std::io::Read,
+    {
+        let mut reader = MarkableReader::with_mark_buffer(
+            inner,
+            Buffer::new(self.mark_capacity, self.mark_limit),
+        );
+        reader.sticky_mark = self.sticky_mark;
+        reader
+    }
+}
+
+impl Default for MarkableReaderBuilder {
+    fn default() -> Self {
+        MarkableReaderBuilder::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read, Seek, SeekFrom, Write};
+
+    use crate::io::{
+        Bom, BoxedMarkerStream, MarkBufferError, MarkerStream, ReadError, DEFAULT_BUFFER_SIZE,
+        DEFAULT_MARKER_BUFFER_SIZE,
+    };
+
+    use super::{MarkableReader, MarkableReaderBuilder};
+    use crate::BufferedMarkableReader;
+
+    #[test]
+    fn test_basic_read() {
+        let input_data = vec![0, 1, 2, 3];
+        let data = Cursor::new(input_data.clone());
+        let mut reader = MarkableReader::new(data);
+
+        let mut read_buf = vec![0; input_data.len()];
+        reader
+            .read_exact(&mut read_buf)
+            .expect("should be able to read bytes back");
+        assert_eq!(
+            input_data, read_buf,
+            "read buffer and input buffer should match"
+        );
+    }
+
+    #[test]
+    fn test_marked_read() {
+        let input_data = vec![0, 1, 2, 3];
+        let data = Cursor::new(input_data.clone());
+        let mut reader = MarkableReader::new(data);
+
+        let mut single_byte_buf = vec![0];
+        reader
+            .read_exact(&mut single_byte_buf)
+            .expect("should be able to read single byte");
+
+        assert_eq!(0, reader.mark(), "no bytes should be wasted");
+
+        let mut rest_of_buf = vec![0; input_data.len() - 1];
+        reader
+            .read_exact(&mut rest_of_buf)
+            .expect("should be able to read rest of buffer");
+
+        reader.reset();
+        rest_of_buf = vec![0; input_data.len() - 1];
+
+        reader
+            .read_exact(&mut rest_of_buf)
+            .expect("should be able to read rest of buffer again after reset");
+
+        assert_eq!(
+            input_data[1..],
+            rest_of_buf,
+            "buffer should be last 3 bytes"
+        );
+    }
+
+    #[test]
+    fn test_back_buffer_and_read_buffer_read() {
+        let input_data = vec![0, 1, 2, 3];
+        let data = Cursor::new(input_data.clone());
+        let mut reader = MarkableReader::new(data);
+
+        let mut half_buf = vec![0; input_data.len() / 2];
+        reader.mark();
+        reader
+            .read_exact(&mut half_buf)
+            .expect("should be able to read half the buffer");
+
+        reader.reset();
+        let mut whole_buf = vec![0; input_data.len()];
+
+        reader
+            .read_exact(&mut whole_buf)
+            .expect("should be able to whole buffer");
+
+        assert_eq!(
+            input_data, whole_buf,
+            "input data and whole buf should match"
+        );
+    }
+
+    #[test]
+    fn test_read_with_popping_bytes() {
+        let input_data = vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+        let data = Cursor::new(input_data.clone());
+        let mut reader = MarkableReader::new(data);
+        let mut single_byte_buffer = vec![0_u8; 1];
+
+        for i in 0..input_data.len() - 1 {
+            reader.mark();
+            let expected = input_data[i..i + 2].to_vec();
+            let mut actual = [0_u8; 2];
+            reader
+                .read_exact(&mut actual)
+                .expect("should always be able to read 2 bytes");
+            assert_eq!(
+                expected, actual,
+                "bytes at index {i} should be {expected:?} but were {actual:?}"
+            );
+
+            reader.reset();
+            reader
+                .read_exact(&mut single_byte_buffer)
+                .expect("should be able to read single byte");
+            assert_eq!(
+                single_byte_buffer[0], input_data[i],
+                "popped byte at index {i} should be {i} but was {}",
+                single_byte_buffer[0]
+            );
+        }
+    }
+
+    #[test]
+    fn test_consecutive_marked_reads() {
+        let input_data = vec![0, 1, 2, 3];
+        let data = Cursor::new(input_data.clone());
+        let mut reader = MarkableReader::new(data);
+
+        reader.mark();
+        let mut first_half = vec![0; 2];
+        let mut second_half = vec![0; 2];
+        reader
+            .read_exact(&mut first_half)
+            .expect("should be able to read first half");
+        reader
+            .read_exact(&mut second_half)
+            .expect("should be able to read second half");
+        assert_eq!(input_data[..2], first_half, "first half should be [0, 1]");
+        assert_eq!(input_data[2..], second_half, "second half should be [2, 3]");
+
+        reader.reset();
+        let mut whole_buf = vec![0; input_data.len()];
+        reader
+            .read_exact(&mut whole_buf)
+            .expect("should be able to replay the whole buffer");
+        assert_eq!(input_data, whole_buf, "replay should match input data");
+    }
+
+    #[test]
+    fn test_peek_then_read() {
+        let input_data = vec![0, 1, 2, 3];
+        let data = Cursor::new(input_data.clone());
+        let mut reader = MarkableReader::new(data);
+
+        let peeked = reader.peek(2).expect("should be able to peek").to_vec();
+        assert_eq!(input_data[..2], peeked, "peeked bytes should be [0, 1]");
+
+        let mut read_buf = vec![0; input_data.len()];
+        reader
+            .read_exact(&mut read_buf)
+            .expect("should be able to read after peeking");
+        assert_eq!(
+            input_data, read_buf,
+            "peeking should not have consumed any bytes"
+        );
+    }
+
+    #[test]
+    fn test_peek_after_reset() {
+        let input_data = vec![0, 1, 2, 3];
+        let data = Cursor::new(input_data.clone());
+        let mut reader = MarkableReader::new(data);
+
+        reader.mark();
+        let mut half_buf = vec![0; 2];
+        reader
+            .read_exact(&mut half_buf)
+            .expect("should be able to read half the buffer");
+        reader.reset();
+
+        let peeked = reader.peek(3).expect("should be able to peek").to_vec();
+        assert_eq!(
+            input_data[..3],
+            peeked,
+            "peek should start with the replayed bytes"
+        );
+
+        let mut whole_buf = vec![0; input_data.len()];
+        reader
+            .read_exact(&mut whole_buf)
+            .expect("should be able to read whole buffer");
+        assert_eq!(
+            input_data, whole_buf,
+            "input data and whole buf should match"
+        );
+    }
+
+    #[test]
+    fn test_peek_past_eof() {
+        let input_data = vec![0, 1, 2, 3];
+        let data = Cursor::new(input_data.clone());
+        let mut reader = MarkableReader::new(data);
+
+        let peeked = reader
+            .peek(input_data.len() * 2)
+            .expect("should be able to peek past the end")
+            .to_vec();
+        assert_eq!(input_data, peeked, "peek should return the remaining bytes");
+    }
+
+    /// Records the offset at which each read that returned data began
+    struct OffsetTrackingReader {
+        inner: Cursor<Vec<u8>>,
+        read_offsets: Vec<u64>,
+    }
+
+    impl Read for OffsetTrackingReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let offset = self.inner.position();
+            let bytes_read = self.inner.read(buf)?;
+            if bytes_read > 0 {
+                self.read_offsets.push(offset);
+            }
+            Ok(bytes_read)
+        }
+    }
+
+    #[test]
+    fn test_aligned_inner_reads() {
+        let input_data: Vec<u8> = (0..30).collect();
+        let data = OffsetTrackingReader {
+            inner: Cursor::new(input_data.clone()),
+            read_offsets: vec![],
+        };
+        let mut reader = MarkableReader::new(data).with_read_alignment(4);
+
+        let mut output = vec![0; 3];
+        reader
+            .read_exact(&mut output)
+            .expect("should be able to read 3 bytes");
+        reader.mark();
+        reader.peek(5).expect("should be able to peek 5 bytes");
+        let mut rest = vec![0; input_data.len() - 3];
+        reader
+            .read_exact(&mut rest)
+            .expect("should be able to read the rest of the stream");
+        output.extend(rest);
+        assert_eq!(
+            input_data, output,
+            "aligned reads should not change the data"
+        );
+
+        let read_offsets = reader.into_inner().read_offsets;
+        assert!(
+            !read_offsets.is_empty(),
+            "should have read from the inner reader"
+        );
+        for offset in read_offsets {
+            assert_eq!(
+                0,
+                offset % 4,
+                "inner read at offset {offset} should be aligned"
+            );
+        }
+    }
+
+    #[test]
+    fn test_read_inner_direct_keeps_alignment() {
+        let input_data: Vec<u8> = (0..30).collect();
+        let data = OffsetTrackingReader {
+            inner: Cursor::new(input_data.clone()),
+            read_offsets: vec![],
+        };
+        let mut reader = MarkableReader::new(data).with_read_alignment(4);
+
+        let mut output = vec![0; 6];
+        assert_eq!(
+            Some(4),
+            reader
+                .read_inner_direct(&mut output)
+                .expect("should be able to read directly"),
+            "direct read should stop at the alignment boundary"
+        );
+        assert_eq!(
+            None,
+            reader
+                .read_inner_direct(&mut output[4..6])
+                .expect("should be able to read directly"),
+            "direct read too small to reach a boundary should be refused"
+        );
+        output.truncate(4);
+        let mut rest = vec![0; input_data.len() - 4];
+        reader
+            .read_exact(&mut rest)
+            .expect("should be able to read the rest of the stream");
+        output.extend(rest);
+        assert_eq!(
+            input_data, output,
+            "direct reads should not change the data"
+        );
+
+        for offset in reader.into_inner().read_offsets {
+            assert_eq!(
+                0,
+                offset % 4,
+                "inner read at offset {offset} should be aligned"
+            );
+        }
+    }
+
+    #[test]
+    fn test_read_inner_direct_refused_when_buffered() {
+        let input_data = vec![0, 1, 2, 3];
+        let data = Cursor::new(input_data.clone());
+        let mut reader = MarkableReader::new(data);
+        let mut buf = vec![0; 2];
+
+        reader.mark();
+        assert_eq!(
+            None,
+            reader.read_inner_direct(&mut buf).unwrap(),
+            "should refuse to read while marked"
+        );
+
+        reader.reset();
+        reader.peek(1).expect("should be able to peek");
+        assert_eq!(
+            None,
+            reader.read_inner_direct(&mut buf).unwrap(),
+            "should refuse to read while bytes are buffered"
+        );
+
+        let mut read_buf = vec![0; input_data.len()];
+        reader
+            .read_exact(&mut read_buf)
+            .expect("should still be able to read the whole stream");
+        assert_eq!(input_data, read_buf, "no bytes should have been lost");
+    }
+
+    #[test]
+    fn test_read_inner_direct() {
+        let input_data = vec![0, 1, 2, 3];
+        let data = Cursor::new(input_data.clone());
+        let mut reader = MarkableReader::new(data);
+
+        let mut buf = vec![0; 2];
+        assert_eq!(
+            Some(2),
+            reader.read_inner_direct(&mut buf).unwrap(),
+            "should read directly from the inner reader"
+        );
+        assert_eq!(
+            input_data[..2],
+            buf,
+            "direct read should be the first 2 bytes"
+        );
+
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read the remaining bytes");
+        assert_eq!(
+            input_data[2..],
+            buf,
+            "read should continue after the direct read"
+        );
+    }
+
+    #[test]
+    fn test_peek_byte_then_read() {
+        let input_data = vec![7, 8];
+        let data = Cursor::new(input_data.clone());
+        let mut reader = MarkableReader::new(data);
+
+        assert_eq!(
+            Some(7),
+            reader.peek_byte().expect("should be able to peek a byte"),
+            "peeked byte should be the first byte"
+        );
+
+        let mut single_byte_buf = vec![0];
+        reader
+            .read_exact(&mut single_byte_buf)
+            .expect("should be able to read the peeked byte");
+        assert_eq!(7, single_byte_buf[0], "read should return the peeked byte");
+    }
+
+    #[test]
+    fn test_peek_byte_at_eof() {
+        let data = Cursor::new(vec![7]);
+        let mut reader = MarkableReader::new(data);
+
+        let mut single_byte_buf = vec![0];
+        reader
+            .read_exact(&mut single_byte_buf)
+            .expect("should be able to read the only byte");
+        assert_eq!(
+            None,
+            reader.peek_byte().expect("peeking at eof should not error"),
+            "should not have a byte to peek at eof"
+        );
+    }
+
+    #[test]
+    fn test_replay_recording() {
+        let input_data: Vec<u8> = (0..20).collect();
+        let data = Cursor::new(input_data.clone());
+        let mut reader = MarkableReader::new(data).with_recording();
+
+        let mut read_buf = vec![0; input_data.len()];
+        reader
+            .read_exact(&mut read_buf)
+            .expect("should be able to read the whole stream");
+
+        let mut replay = reader.replay();
+        let mut replay_buf = vec![0; input_data.len()];
+        replay
+            .read_exact(&mut replay_buf)
+            .expect("should be able to read the whole replay");
+        assert_eq!(input_data, replay_buf, "replay should match the input data");
+    }
+
+    #[test]
+    fn test_limited_recording() {
+        let input_data: Vec<u8> = (0..20).collect();
+        let data = Cursor::new(input_data.clone());
+        let mut reader = MarkableReader::new(data).with_limited_recording(4);
+
+        let mut read_buf = vec![0; input_data.len()];
+        match reader.read_exact(&mut read_buf) {
+            Err(err) => assert_eq!(
+                std::io::ErrorKind::OutOfMemory,
+                err.kind(),
+                "should have had an out of memory error"
+            ),
+            _ => panic!("should have exceeded the recording limit"),
+        }
+    }
+
+    #[test]
+    fn test_limited_recording_loses_no_bytes() {
+        let input_data: Vec<u8> = (0..20).collect();
+        let data = Cursor::new(input_data.clone());
+        let mut reader = MarkableReader::new(data).with_limited_recording(4);
+
+        let mut read_buf = vec![0; 6];
+        assert_eq!(
+            4,
+            reader
+                .read(&mut read_buf)
+                .expect("should read up to the limit"),
+            "should stop at the recording limit"
+        );
+        assert_eq!(
+            input_data[..4],
+            read_buf[..4],
+            "should read the recorded bytes"
+        );
+        for _ in 0..2 {
+            assert_eq!(
+                std::io::ErrorKind::OutOfMemory,
+                reader.read(&mut read_buf).unwrap_err().kind(),
+                "a full recording should fail reads"
+            );
+        }
+        assert_eq!(
+            4,
+            reader.get_ref().position(),
+            "failed reads should take nothing from the inner reader"
+        );
+        assert_eq!(4, reader.position(), "failed reads should consume nothing");
+    }
+
+    #[test]
+    fn test_position_across_reset() {
+        let input_data = vec![0, 1, 2, 3, 4, 5];
+        let data = Cursor::new(input_data.clone());
+        let mut reader = MarkableReader::new(data);
+
+        let mut buf = vec![0; 2];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 2 bytes");
+        assert_eq!(
+            2,
+            reader.position(),
+            "position should be 2 after first read"
+        );
+
+        reader.mark();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 2 more bytes");
+        assert_eq!(
+            4,
+            reader.position(),
+            "position should be 4 after marked read"
+        );
+
+        reader.reset();
+        assert_eq!(2, reader.position(), "position should return to the mark");
+
+        let mut rest = vec![0; 4];
+        reader
+            .read_exact(&mut rest)
+            .expect("should be able to read the rest of the stream");
+        assert_eq!(
+            input_data.len() as u64,
+            reader.position(),
+            "replayed bytes should only be counted once"
+        );
+    }
+
+    #[test]
+    fn test_absorb_from() {
+        let input_data = vec![0, 1, 2, 3, 4, 5];
+        let mut data = Cursor::new(input_data.clone());
+        let mut handed_off = vec![0; 2];
+        data.read_exact(&mut handed_off)
+            .expect("should be able to read the bytes being handed off");
+
+        let mut reader = MarkableReader::new(data);
+        reader.peek(1).expect("should be able to peek");
+        reader
+            .absorb_from(handed_off)
+            .expect("should be able to absorb bytes");
+
+        let mut read_buf = vec![0; input_data.len()];
+        reader
+            .read_exact(&mut read_buf)
+            .expect("should be able to read the whole stream");
+        assert_eq!(input_data, read_buf, "absorbed bytes should be read first");
+    }
+
+    #[test]
+    fn test_nested_marks() {
+        let input_data = vec![0, 1, 2, 3, 4, 5];
+        let data = Cursor::new(input_data.clone());
+        let mut reader = MarkableReader::new(data);
+        let mut buf = vec![0; 2];
+
+        let outer = reader.push_mark();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 2 bytes");
+        let inner = reader.push_mark();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 2 more bytes");
+
+        reader.pop_mark(inner);
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to re-read the inner region");
+        assert_eq!(
+            input_data[2..4],
+            buf,
+            "inner pop should only rewind the inner region"
+        );
+
+        reader.pop_mark(outer);
+        let mut whole_buf = vec![0; input_data.len()];
+        reader
+            .read_exact(&mut whole_buf)
+            .expect("should be able to re-read the whole stream");
+        assert_eq!(
+            input_data, whole_buf,
+            "outer pop should rewind to the start"
+        );
+    }
+
+    #[test]
+    fn test_commit_nested_mark() {
+        let input_data = vec![0, 1, 2, 3, 4, 5];
+        let data = Cursor::new(input_data.clone());
+        let mut reader = MarkableReader::new(data);
+        let mut buf = vec![0; 2];
+
+        let outer = reader.push_mark();
+        let inner = reader.push_mark();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 2 bytes");
+        reader.commit_mark(inner);
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 2 more bytes");
+
+        reader.pop_mark(outer);
+        let mut replay_buf = vec![0; 4];
+        reader
+            .read_exact(&mut replay_buf)
+            .expect("should be able to replay from the outer mark");
+        assert_eq!(
+            input_data[..4],
+            replay_buf,
+            "committing the inner mark should keep the outer region"
+        );
+    }
+
+    #[test]
+    fn test_overwrite_named_mark() {
+        let input_data = vec![0, 1, 2, 3, 4, 5];
+        let data = Cursor::new(input_data.clone());
+        let mut reader = MarkableReader::new(data);
+        let mut buf = vec![0; 2];
+
+        reader.mark_named("checkpoint");
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 2 bytes");
+        reader.mark_named("checkpoint");
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 2 more bytes");
+
+        assert!(reader.reset_named("checkpoint"), "name should be known");
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to re-read from the checkpoint");
+        assert_eq!(
+            input_data[2..4],
+            buf,
+            "reset should go to the latest position for the name"
+        );
+    }
+
+    #[test]
+    fn test_reset_to_stale_named_mark() {
+        let input_data = vec![0, 1, 2, 3, 4, 5];
+        let data = Cursor::new(input_data.clone());
+        let mut reader = MarkableReader::new(data);
+        let mut buf = vec![0; 2];
+
+        reader.mark_named("first");
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 2 bytes");
+        reader.mark_named("second");
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 2 more bytes");
+
+        assert!(reader.reset_named("first"), "first mark should be known");
+        assert!(
+            !reader.reset_named("second"),
+            "marks set after the first should have been invalidated"
+        );
+        assert!(
+            !reader.reset_named("unknown"),
+            "unknown names should not reset"
+        );
+
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to re-read from the first mark");
+        assert_eq!(input_data[..2], buf, "stream should be at the first mark");
+
+        reader.clear_buffer();
+        assert!(
+            !reader.reset_named("first"),
+            "clearing the buffer should invalidate named marks"
+        );
+    }
+
+    #[test]
+    fn test_is_marked() {
+        let data = Cursor::new(vec![0, 1, 2, 3]);
+        let mut reader = MarkableReader::new(data);
+        assert!(!reader.is_marked(), "new reader should not be marked");
+
+        reader.mark();
+        assert!(reader.is_marked(), "reader should be marked after mark");
+        reader.reset();
+        assert!(
+            !reader.is_marked(),
+            "reader should not be marked after reset"
+        );
+
+        reader.mark();
+        reader.clear_buffer();
+        assert!(
+            !reader.is_marked(),
+            "reader should not be marked after clear_buffer"
+        );
+    }
+
+    #[test]
+    fn test_buffered_len() {
+        let data = Cursor::new(vec![0, 1, 2, 3, 4, 5]);
+        let mut reader = MarkableReader::new(data);
+        let mut buf = vec![0; 3];
+
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 3 bytes");
+        assert_eq!(
+            0,
+            reader.buffered_len(),
+            "unmarked reader should not buffer"
+        );
+
+        reader.mark();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 3 more bytes");
+        assert_eq!(3, reader.buffered_len(), "should have buffered 3 bytes");
+
+        reader.reset();
+        assert_eq!(0, reader.buffered_len(), "reset reader is no longer marked");
+    }
+
+    #[test]
+    fn test_seek_backward_into_buffer() {
+        let input_data = vec![0, 1, 2, 3, 4, 5];
+        let data = Cursor::new(input_data.clone());
+        let mut reader = MarkableReader::new(data);
+        let mut buf = vec![0; 4];
+
+        reader.mark();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 4 bytes");
+        assert_eq!(
+            4,
+            reader.stream_position().unwrap(),
+            "stream position should account for buffering"
+        );
+        assert_eq!(
+            2,
+            reader.seek(SeekFrom::Current(-2)).unwrap(),
+            "should have moved back 2 bytes"
+        );
+        assert!(
+            reader.is_marked(),
+            "seeking within the buffer should keep the mark"
+        );
+
+        let mut rest = vec![0; 4];
+        reader
+            .read_exact(&mut rest)
+            .expect("should be able to read from the seeked position");
+        assert_eq!(
+            input_data[2..],
+            rest,
+            "should read from the seeked position"
+        );
+    }
+
+    #[test]
+    fn test_seek_past_buffer() {
+        let input_data = vec![0, 1, 2, 3, 4, 5];
+        let data = Cursor::new(input_data.clone());
+        let mut reader = MarkableReader::new(data);
+        let mut buf = vec![0; 2];
+
+        reader.mark();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 2 bytes");
+        reader.peek(2).expect("should be able to peek");
+        assert_eq!(
+            3,
+            reader.seek(SeekFrom::Current(1)).unwrap(),
+            "should have moved forward 1 byte"
+        );
+        assert!(
+            !reader.is_marked(),
+            "seeking the inner reader should clear the mark"
+        );
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read from the seeked position");
+        assert_eq!(
+            input_data[3..5],
+            buf,
+            "should read from the seeked position"
+        );
+
+        assert_eq!(
+            0,
+            reader.seek(SeekFrom::Start(0)).unwrap(),
+            "should be at the start"
+        );
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read from the start");
+        assert_eq!(input_data[..2], buf, "should read from the start");
+    }
+
+    #[test]
+    fn test_get_ref_on_file() {
+        let path = std::env::temp_dir().join(format!(
+            "markable_reader_get_ref_{}.bin",
+            std::process::id()
+        ));
+        std::fs::write(&path, [0, 1, 2, 3]).expect("should be able to write temp file");
+
+        let file = std::fs::File::open(&path).expect("should be able to open temp file");
+        let reader = MarkableReader::new(file);
+        let metadata = reader
+            .get_ref()
+            .metadata()
+            .expect("should be able to query file metadata");
+        assert_eq!(4, metadata.len(), "file should contain 4 bytes");
+
+        std::fs::remove_file(&path).expect("should be able to remove temp file");
+    }
+
+    #[test]
+    fn test_skip_buffered_bytes() {
+        let input_data = vec![0, 1, 2, 3, 4, 5];
+        let data = Cursor::new(input_data.clone());
+        let mut reader = MarkableReader::new(data);
+
+        reader.peek(4).expect("should be able to peek");
+        assert_eq!(3, reader.skip(3).unwrap(), "should have skipped 3 bytes");
+
+        let mut rest = vec![0; 3];
+        reader
+            .read_exact(&mut rest)
+            .expect("should be able to read the rest of the stream");
+        assert_eq!(input_data[3..], rest, "should read after the skipped bytes");
+    }
+
+    #[test]
+    fn test_skip_past_eof() {
+        let data = Cursor::new(vec![0, 1, 2, 3]);
+        let mut reader = MarkableReader::new(data);
+
+        assert_eq!(
+            4,
+            reader.skip(10).unwrap(),
+            "should skip to the end of the stream"
+        );
+        assert_eq!(0, reader.skip(1).unwrap(), "nothing should be left to skip");
+    }
+
+    #[test]
+    fn test_skip_while_marked() {
+        let input_data = vec![0, 1, 2, 3, 4, 5];
+        let data = Cursor::new(input_data.clone());
+        let mut reader = MarkableReader::new(data);
+
+        reader.mark();
+        assert_eq!(4, reader.skip(4).unwrap(), "should have skipped 4 bytes");
+        reader.reset();
+
+        let mut whole_buf = vec![0; input_data.len()];
+        reader
+            .read_exact(&mut whole_buf)
+            .expect("should be able to read the whole stream");
+        assert_eq!(input_data, whole_buf, "skipped bytes should be replayed");
+    }
+
+    #[test]
+    fn test_read_until_across_buffer_boundary() {
+        let input_data = b"first\nsecond record\n".to_vec();
+        let data = Cursor::new(input_data.clone());
+        let mut reader = MarkableReader::new(data);
+
+        reader.mark();
+        let mut partial = vec![0; 8];
+        reader
+            .read_exact(&mut partial)
+            .expect("should be able to read into the second record");
+        reader.reset();
+
+        let mut first = Vec::new();
+        assert_eq!(6, reader.read_until(b'\n', &mut first).unwrap());
+        assert_eq!(b"first\n".to_vec(), first, "should read the first record");
+
+        let mut second = Vec::new();
+        assert_eq!(14, reader.read_until(b'\n', &mut second).unwrap());
+        assert_eq!(
+            b"second record\n".to_vec(),
+            second,
+            "should read the second record across the buffer boundary"
+        );
+
+        let mut rest = Vec::new();
+        assert_eq!(
+            0,
+            reader.read_until(b'\n', &mut rest).unwrap(),
+            "should be at eof"
+        );
+    }
+
+    #[test]
+    fn test_read_until_while_marked() {
+        let input_data = b"a,b".to_vec();
+        let data = Cursor::new(input_data.clone());
+        let mut reader = MarkableReader::new(data);
+
+        reader.mark();
+        let mut record = Vec::new();
+        reader
+            .read_until(b',', &mut record)
+            .expect("should be able to read a record");
+        assert_eq!(b"a,".to_vec(), record, "should include the delimiter");
+        reader.reset();
+
+        let mut whole_buf = vec![0; input_data.len()];
+        reader
+            .read_exact(&mut whole_buf)
+            .expect("should be able to read the whole stream");
+        assert_eq!(
+            input_data, whole_buf,
+            "record should be replayed after reset"
+        );
+    }
+
+    #[test]
+    fn test_bytes_iter() {
+        let input_data = vec![0, 1, 2, 3];
+        let data = Cursor::new(input_data.clone());
+        let mut reader = MarkableReader::new(data);
+
+        let output: Vec<u8> = reader
+            .bytes_iter()
+            .collect::<std::io::Result<_>>()
+            .expect("should be able to iterate the stream");
+        assert_eq!(input_data, output, "iterated bytes should match the input");
+    }
+
+    #[test]
+    fn test_bytes_iter_mark_and_reset() {
+        let input_data = vec![0, 1, 2, 3];
+        let data = Cursor::new(input_data.clone());
+        let mut reader = MarkableReader::new(data);
+
+        let mut bytes = reader.bytes_iter();
+        bytes.mark();
+        assert_eq!(0, bytes.next().unwrap().unwrap(), "first byte should be 0");
+        assert_eq!(1, bytes.next().unwrap().unwrap(), "second byte should be 1");
+        bytes.reset();
+
+        let output: Vec<u8> = bytes
+            .collect::<std::io::Result<_>>()
+            .expect("should be able to iterate the stream");
+        assert_eq!(input_data, output, "reset should replay the iterated bytes");
+    }
+
+    #[test]
+    fn test_into_parts() {
+        let input_data: Vec<u8> = (0..10).collect();
+        let data = Cursor::new(input_data.clone());
+        let mut reader = MarkableReader::new(data);
+        let mut buf = vec![0; 3];
+
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 3 bytes");
+        reader.mark();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 3 more bytes");
+        reader.peek(2).expect("should be able to peek");
+        reader.reset();
+
+        let (mut inner, mut buffered) = reader.into_parts();
+        assert!(
+            buffered.starts_with(&input_data[3..8]),
+            "buffered bytes should start with the replayed and peeked bytes"
+        );
+        inner
+            .read_to_end(&mut buffered)
+            .expect("should be able to read the rest of the inner reader");
+        assert_eq!(
+            input_data[3..],
+            buffered,
+            "buffered bytes and inner reader should hold the unconsumed bytes"
+        );
+    }
+
+    #[test]
+    fn test_into_parts_handoff() {
+        let input_data: Vec<u8> = (0..10).collect();
+        let data = Cursor::new(input_data.clone());
+        let mut reader = MarkableReader::new(data);
+        let mut buf = vec![0; 3];
+
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 3 bytes");
+        reader.peek(4).expect("should be able to peek");
+
+        let (inner, buffered) = reader.into_parts();
+        let mut upgraded = BufferedMarkableReader::new(inner);
+        upgraded
+            .absorb_from(buffered)
+            .expect("should be able to absorb the buffered bytes");
+
+        let mut rest = vec![0; input_data.len() - 3];
+        upgraded
+            .read_exact(&mut rest)
+            .expect("should be able to read the rest of the stream");
+        assert_eq!(
+            input_data[3..],
+            rest,
+            "no bytes should be lost in the handoff"
+        );
+    }
+
+    #[test]
+    fn test_raise_mark_limit() {
+        let input_data = vec![0, 1, 2, 3];
+        let mut reader =
+            MarkableReader::new_with_limited_back_buffer(Cursor::new(input_data.clone()), 2);
+        let mut buf = vec![0; input_data.len()];
+        reader.mark();
+        assert_eq!(
+            std::io::ErrorKind::OutOfMemory,
+            reader.read_exact(&mut buf).unwrap_err().kind(),
+            "read should exceed the original limit"
+        );
+
+        let mut reader =
+            MarkableReader::new_with_limited_back_buffer(Cursor::new(input_data.clone()), 2);
+        reader
+            .set_mark_limit(Some(input_data.len()))
+            .expect("should be able to raise the limit");
+        reader.mark();
+        reader
+            .read_exact(&mut buf)
+            .expect("read should fit within the raised limit");
+        reader.reset();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to replay the marked read");
+        assert_eq!(input_data, buf, "replay should match the input data");
+    }
+
+    #[test]
+    fn test_lower_mark_limit_below_usage() {
+        let input_data = vec![0, 1, 2, 3];
+        let mut reader = MarkableReader::new(Cursor::new(input_data.clone()));
+        let mut buf = vec![0; input_data.len()];
+        reader.mark();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 4 bytes");
+
+        assert_eq!(
+            std::io::ErrorKind::InvalidInput,
+            reader.set_mark_limit(Some(2)).unwrap_err().kind(),
+            "should not be able to lower the limit below usage"
+        );
+
+        reader.reset();
+        reader
+            .read_exact(&mut buf)
+            .expect("should still be able to replay the marked read");
+        assert_eq!(input_data, buf, "buffered bytes should be intact");
+    }
+
+    #[test]
+    fn test_clone_replays_independently() {
+        let input_data = vec![0, 1, 2, 3, 4, 5];
+        let mut reader = MarkableReader::new(Cursor::new(input_data.clone()));
+        let mut buf = vec![0; 2];
+        reader.mark();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 2 bytes");
+
+        let mut clone = reader.clone();
+        let mut clone_buf = vec![0; 2];
+        clone
+            .read_exact(&mut clone_buf)
+            .expect("clone should be able to read 2 bytes");
+        assert_eq!(
+            vec![2, 3],
+            clone_buf,
+            "clone should continue from the original"
+        );
+
+        reader.reset();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to replay");
+        assert_eq!(vec![0, 1], buf, "original should replay its marked bytes");
+
+        clone.reset();
+        let mut clone_buf = vec![0; 4];
+        clone
+            .read_exact(&mut clone_buf)
+            .expect("clone should be able to replay");
+        assert_eq!(
+            vec![0, 1, 2, 3],
+            clone_buf,
+            "clone should replay its own marked bytes"
+        );
+
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 2 bytes");
+        assert_eq!(
+            vec![2, 3],
+            buf,
+            "original should be unaffected by the clone"
+        );
+    }
+
+    #[test]
+    fn test_debug_summarizes_state() {
+        let mut reader =
+            MarkableReader::new_with_limited_back_buffer(Cursor::new(vec![0, 1, 2, 3]), 8);
+        let mut buf = vec![0; 2];
+        reader.mark();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 2 bytes");
+
+        let formatted = format!("{:?}", reader);
+        assert!(formatted.contains("inner: Cursor"), "{}", formatted);
+        assert!(formatted.contains("is_marked: true"), "{}", formatted);
+        assert!(formatted.contains("mark_buffer_len: 2"), "{}", formatted);
+        assert!(formatted.contains("mark_limit: Some(8)"), "{}", formatted);
+        assert!(formatted.contains("peek_buffer_len: "), "{}", formatted);
+    }
+
+    #[test]
+    fn test_builder_without_limit_is_unbounded() {
+        let input_data = vec![7; 16];
+        let mut reader = MarkableReaderBuilder::new()
+            .mark_capacity(4)
+            .build(Cursor::new(input_data.clone()));
+        assert_eq!(
+            None,
+            reader.mark_buffer.limit(),
+            "mark buffer should be unbounded"
+        );
+        assert_eq!(
+            MarkableReader::new(Cursor::new(input_data.clone()))
+                .mark_buffer
+                .limit(),
+            reader.mark_buffer.limit(),
+            "limit should match the default constructor"
+        );
+
+        let mut buf = vec![0; input_data.len()];
+        reader.mark();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read past the initial capacity");
+        reader.reset();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to replay");
+        assert_eq!(input_data, buf, "replay should match the input data");
+    }
+
+    #[test]
+    fn test_builder_matches_capacity_and_limit_constructor() {
+        let built = MarkableReaderBuilder::new()
+            .mark_capacity(16)
+            .mark_limit(32)
+            .build(Cursor::new(vec![0, 1, 2]));
+        let constructed =
+            MarkableReader::new_with_capacity_and_limit(Cursor::new(vec![0, 1, 2]), 16, 32);
+
+        assert_eq!(
+            constructed.mark_buffer, built.mark_buffer,
+            "mark buffers should match"
+        );
+        assert_eq!(
+            constructed.peek_buffer, built.peek_buffer,
+            "peek buffers should match"
+        );
+        assert_eq!(
+            constructed.is_marked, built.is_marked,
+            "marked state should match"
+        );
+    }
+
+    struct DuplexStream {
+        input: Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+
+    impl Read for DuplexStream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+
+    impl Write for DuplexStream {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.output.write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            self.output.flush()
+        }
+    }
+
+    #[test]
+    fn test_write_passes_through_to_inner() {
+        let mut reader = MarkableReader::new(DuplexStream {
+            input: Cursor::new(vec![0, 1, 2, 3]),
+            output: Vec::new(),
+        });
+        let mut buf = vec![0; 2];
+        reader.mark();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 2 bytes");
+
+        reader
+            .write_all(&[9, 8, 7])
+            .expect("should be able to write to the inner stream");
+        reader
+            .flush()
+            .expect("should be able to flush the inner stream");
+        assert_eq!(
+            vec![9, 8, 7],
+            reader.get_ref().output,
+            "writes should reach the inner stream"
+        );
+
+        reader.reset();
+        let mut buf = vec![0; 4];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to replay and continue");
+        assert_eq!(
+            vec![0, 1, 2, 3],
+            buf,
+            "reads should be unaffected by writes"
+        );
+    }
+
+    #[test]
+    fn test_mark_guard_resets_on_drop() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![0, 1, 2, 3]));
+        let mut buf = vec![0; 2];
+        {
+            let mut guard = reader.mark_guard();
+            guard
+                .read_exact(&mut buf)
+                .expect("should be able to read through the guard");
+            assert_eq!(vec![0, 1], buf, "guard should read from the start");
+        }
+
+        assert!(
+            !reader.is_marked(),
+            "reader should not be marked after the guard is dropped"
+        );
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 2 bytes");
+        assert_eq!(
+            vec![0, 1],
+            buf,
+            "dropping the guard should rewind the stream"
+        );
+    }
+
+    #[test]
+    fn test_mark_guard_commit_advances() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![0, 1, 2, 3]));
+        let mut buf = vec![0; 2];
+        reader.mark();
+        {
+            let mut guard = reader.mark_guard();
+            guard
+                .read_exact(&mut buf)
+                .expect("should be able to read through the guard");
+            guard.commit();
+        }
+
+        assert!(reader.is_marked(), "outer mark should remain after commit");
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 2 bytes");
+        assert_eq!(
+            vec![2, 3],
+            buf,
+            "committing should keep the stream advanced"
+        );
+
+        reader.reset();
+        let mut buf = vec![0; 4];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to replay the outer mark");
+        assert_eq!(
+            vec![0, 1, 2, 3],
+            buf,
+            "outer mark should include the committed reads"
+        );
+    }
+
+    #[test]
+    fn test_try_reset_reports_mark() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![0, 1, 2, 3]));
+        assert!(!reader.try_reset(), "fresh reader should not be reset");
+
+        let mut buf = vec![0; 2];
+        reader.mark();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 2 bytes");
+        assert!(reader.try_reset(), "marked reader should be reset");
+        assert!(!reader.try_reset(), "reader should no longer be marked");
+
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to replay 2 bytes");
+        assert_eq!(vec![0, 1], buf, "reset should rewind to the mark");
+    }
+
+    #[test]
+    fn test_reset_when_unmarked_keeps_position() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![0, 1, 2, 3]));
+        let mut buf = vec![0; 2];
+        reader.mark();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 2 bytes");
+        reader.reset();
+
+        let mut single_byte = [0; 1];
+        reader
+            .read_exact(&mut single_byte)
+            .expect("should be able to read 1 byte");
+        reader.reset();
+        reader
+            .read_exact(&mut single_byte)
+            .expect("should be able to read 1 byte");
+        assert_eq!(
+            [1],
+            single_byte,
+            "unmarked reset should not rewind the replay"
+        );
+    }
+
+    #[test]
+    fn test_advance_mark() {
+        let input_data = vec![0, 1, 2, 3, 4, 5];
+        let mut reader = MarkableReader::new(Cursor::new(input_data.clone()));
+        let mut buf = vec![0; 3];
+        reader.mark();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 3 bytes");
+        assert_eq!(
+            3,
+            reader.advance_mark(),
+            "bytes read before the advance should be dropped"
+        );
+
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 3 bytes");
+        assert_eq!(
+            vec![3, 4, 5],
+            buf,
+            "should continue after the advanced mark"
+        );
+        reader.reset();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to replay 3 bytes");
+        assert_eq!(
+            vec![3, 4, 5],
+            buf,
+            "reset should rewind to the advanced mark"
+        );
+    }
+
+    #[test]
+    fn test_advance_mark_keeps_unread_replay() {
+        let input_data = vec![0, 1, 2, 3];
+        let mut reader = MarkableReader::new(Cursor::new(input_data.clone()));
+        let mut buf = vec![0; 4];
+        reader.mark();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 4 bytes");
+        reader.reset();
+
+        let mut single_byte = [0; 1];
+        reader.mark();
+        reader
+            .read_exact(&mut single_byte)
+            .expect("should be able to read 1 byte");
+        assert_eq!(
+            1,
+            reader.advance_mark(),
+            "only the read byte should be dropped"
+        );
+
+        let mut buf = vec![0; 3];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read the replayed bytes");
+        assert_eq!(vec![1, 2, 3], buf, "unread replay should be kept");
+        reader.reset();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to replay 3 bytes");
+        assert_eq!(
+            vec![1, 2, 3],
+            buf,
+            "reset should rewind to the advanced mark"
+        );
+    }
+
+    #[test]
+    fn test_peek_exact() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![0, 1, 2, 3]));
+        assert_eq!(
+            &[0, 1, 2],
+            reader
+                .peek_exact(3)
+                .expect("should be able to peek 3 bytes"),
+            "should peek exactly 3 bytes"
+        );
+
+        let mut buf = vec![0; 4];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 4 bytes");
+        assert_eq!(vec![0, 1, 2, 3], buf, "peeked bytes should still be read");
+    }
+
+    #[test]
+    fn test_peek_exact_past_eof() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![0, 1, 2, 3]));
+        assert_eq!(
+            std::io::ErrorKind::UnexpectedEof,
+            reader.peek_exact(5).unwrap_err().kind(),
+            "should not be able to peek past the end of the stream"
+        );
+
+        let mut buf = vec![0; 4];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 4 bytes");
+        assert_eq!(
+            vec![0, 1, 2, 3],
+            buf,
+            "available bytes should not be consumed"
+        );
+    }
+
+    #[test]
+    fn test_peek_exact_spanning_fills() {
+        let input_data: Vec<u8> = (0..3 * DEFAULT_BUFFER_SIZE).map(|i| i as u8).collect();
+        let (head, tail) = input_data.split_at(DEFAULT_BUFFER_SIZE / 2);
+        let mut reader =
+            MarkableReader::new(Cursor::new(head.to_vec()).chain(Cursor::new(tail.to_vec())));
+
+        let peeked = reader
+            .peek_exact(2 * DEFAULT_BUFFER_SIZE)
+            .expect("should be able to peek beyond the buffer size");
+        assert_eq!(
+            &input_data[..2 * DEFAULT_BUFFER_SIZE],
+            peeked,
+            "peek should span reads"
+        );
+
+        let mut buf = vec![0; input_data.len()];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read all bytes");
+        assert_eq!(input_data, buf, "peeked bytes should be read in order");
+    }
+
+    #[test]
+    fn test_take_marked() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![0, 1, 2, 3, 4, 5]));
+        let mut buf = vec![0; 1];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 1 byte");
+        assert!(
+            reader.take_marked().is_empty(),
+            "unmarked reader should return nothing"
+        );
+
+        let mut buf = vec![0; 3];
+        reader.mark();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 3 bytes");
+        assert_eq!(
+            vec![1, 2, 3],
+            reader.take_marked(),
+            "should return the marked reads"
+        );
+        assert!(!reader.is_marked(), "reader should no longer be marked");
+
+        let mut buf = vec![0; 2];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 2 bytes");
+        assert_eq!(vec![4, 5], buf, "stream should stay advanced");
+    }
+
+    #[test]
+    fn test_take_marked_keeps_unread_replay() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![0, 1, 2, 3]));
+        let mut buf = vec![0; 4];
+        reader.mark();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 4 bytes");
+        reader.reset();
+
+        let mut buf = vec![0; 1];
+        reader.mark();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 1 byte");
+        assert_eq!(
+            vec![0],
+            reader.take_marked(),
+            "should return only the marked read"
+        );
+
+        let mut buf = vec![0; 3];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read the replayed bytes");
+        assert_eq!(vec![1, 2, 3], buf, "unread replay should be kept");
+    }
+
+    /// Counts the allocations made by the current thread, so tests running in
+    /// parallel don't affect each other's counts
+    struct CountingAllocator;
+
+    thread_local! {
+        static ALLOCATIONS: std::cell::Cell<usize> = const { std::cell::Cell::new(0) };
+    }
+
+    unsafe impl std::alloc::GlobalAlloc for CountingAllocator {
+        unsafe fn alloc(&self, layout: std::alloc::Layout) -> *mut u8 {
+            let _ = ALLOCATIONS.try_with(|allocations| allocations.set(allocations.get() + 1));
+            unsafe { std::alloc::System.alloc(layout) }
+        }
+
+        unsafe fn dealloc(&self, ptr: *mut u8, layout: std::alloc::Layout) {
+            unsafe { std::alloc::System.dealloc(ptr, layout) }
+        }
+    }
+
+    #[global_allocator]
+    static GLOBAL: CountingAllocator = CountingAllocator;
+
+    #[test]
+    fn test_buffered_in_buffer_peek_does_not_allocate() {
+        let mut reader = BufferedMarkableReader::new(Cursor::new((0..32).collect::<Vec<u8>>()));
+        reader
+            .peek(16)
+            .expect("should be able to fill the read buffer");
+
+        let before = ALLOCATIONS.with(|allocations| allocations.get());
+        let peeked_first = reader.peek(4).expect("should be able to peek 4 bytes")[0];
+        let after = ALLOCATIONS.with(|allocations| allocations.get());
+        assert_eq!(0, after - before, "an in-buffer peek should not allocate");
+        assert_eq!(0, peeked_first, "should peek the buffered bytes");
+    }
+
+    fn count_read_allocations(length: usize) -> usize {
+        let mut reader = MarkableReader::new(Cursor::new(vec![5; length]));
+        let mut buf = vec![0; length];
+        let before = ALLOCATIONS.with(|allocations| allocations.get());
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read all bytes");
+        ALLOCATIONS.with(|allocations| allocations.get()) - before
+    }
+
+    #[test]
+    fn test_inner_reads_do_not_allocate_per_byte() {
+        let small = count_read_allocations(16);
+        let large = count_read_allocations(16 * 1024);
+        assert_eq!(
+            small, large,
+            "allocations should not scale with the bytes read"
+        );
+    }
+
+    struct InterruptingReader {
+        inner: Cursor<Vec<u8>>,
+        interrupted: bool,
+    }
+
+    impl Read for InterruptingReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            if !self.interrupted {
+                self.interrupted = true;
+                return Err(std::io::Error::from(std::io::ErrorKind::Interrupted));
+            }
+
+            self.inner.read(buf)
+        }
+    }
+
+    #[test]
+    fn test_interrupted_reads_are_retried() {
+        let input_data = vec![0, 1, 2, 3];
+        let mut reader = MarkableReader::new(InterruptingReader {
+            inner: Cursor::new(input_data.clone()),
+            interrupted: false,
+        });
+        let mut buf = vec![0; input_data.len()];
+        reader.mark();
+        reader
+            .read_exact(&mut buf)
+            .expect("interrupted read should be retried");
+        assert!(
+            reader.get_ref().interrupted,
+            "inner reader should have been interrupted"
+        );
+        assert_eq!(input_data, buf, "bytes should come through transparently");
+
+        reader.reset();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to replay");
+        assert_eq!(input_data, buf, "replay should match the input data");
+    }
+
+    #[test]
+    fn test_reclaim_releases_mark_buffer() {
+        let input_data = vec![3; 64 * 1024];
+        let mut reader = MarkableReader::new(Cursor::new(input_data.clone()));
+        let mut buf = vec![0; input_data.len()];
+        reader.mark();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read all bytes");
+        reader.reset();
+
+        let mut partial = vec![0; 1024];
+        reader
+            .read_exact(&mut partial)
+            .expect("should be able to replay 1024 bytes");
+        reader.reclaim();
+        let grown_capacity = reader.mark_buffer.capacity();
+        assert!(
+            grown_capacity >= input_data.len() - partial.len(),
+            "replay should be kept"
+        );
+
+        let mut rest = vec![0; input_data.len() - partial.len()];
+        reader
+            .read_exact(&mut rest)
+            .expect("should be able to replay the rest");
+        reader.reclaim();
+        assert!(
+            reader.mark_buffer.capacity() < grown_capacity,
+            "capacity should be reduced once drained"
+        );
+        assert_eq!(
+            0,
+            reader.mark_buffer.capacity(),
+            "drained buffer should be released"
+        );
+    }
+
+    #[test]
+    fn test_read_vectored() {
+        let input_data = vec![0, 1, 2, 3, 4, 5, 6, 7];
+        let mut reader = MarkableReader::new(Cursor::new(input_data.clone()));
+        let (mut first, mut second, mut third) = ([0; 2], [0; 3], [0; 3]);
+        reader.mark();
+        let bytes_read = reader
+            .read_vectored(&mut [
+                std::io::IoSliceMut::new(&mut first),
+                std::io::IoSliceMut::new(&mut second),
+                std::io::IoSliceMut::new(&mut third),
+            ])
+            .expect("should be able to read into all slices");
+        assert_eq!(input_data.len(), bytes_read, "all slices should be filled");
+        assert_eq!([0, 1], first, "first slice should hold the first bytes");
+        assert_eq!([2, 3, 4], second, "second slice should continue the stream");
+        assert_eq!([5, 6, 7], third, "third slice should continue the stream");
+
+        reader.reset();
+        let mut buf = vec![0; input_data.len()];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to replay");
+        assert_eq!(input_data, buf, "bytes from every slice should be retained");
+    }
+
+    #[test]
+    fn test_skip_utf8_bom() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![0xEF, 0xBB, 0xBF, b'h', b'i']));
+        assert_eq!(
+            Some(Bom::Utf8),
+            reader
+                .skip_bom()
+                .expect("should be able to check for a BOM"),
+            "should find the UTF-8 BOM"
+        );
+
+        let mut buf = vec![0; 2];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 2 bytes");
+        assert_eq!(b"hi".to_vec(), buf, "BOM should be consumed");
+    }
+
+    #[test]
+    fn test_skip_utf16_bom() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![0xFF, 0xFE, b'h', 0]));
+        assert_eq!(
+            Some(Bom::Utf16Le),
+            reader
+                .skip_bom()
+                .expect("should be able to check for a BOM"),
+            "should find the UTF-16 LE BOM"
+        );
+
+        let mut buf = vec![0; 2];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 2 bytes");
+        assert_eq!(vec![b'h', 0], buf, "only the BOM should be consumed");
+    }
+
+    #[test]
+    fn test_skip_bom_absent() {
+        let mut reader = MarkableReader::new(Cursor::new(b"hello".to_vec()));
+        assert_eq!(
+            None,
+            reader
+                .skip_bom()
+                .expect("should be able to check for a BOM"),
+            "should not find a BOM"
+        );
+        assert!(!reader.is_marked(), "reader should not be left marked");
+
+        let mut buf = vec![0; 5];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 5 bytes");
+        assert_eq!(b"hello".to_vec(), buf, "stream should be unchanged");
+    }
+
+    #[test]
+    fn test_skip_bom_truncated_stream() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![0xEF, 0xBB]));
+        assert_eq!(
+            None,
+            reader
+                .skip_bom()
+                .expect("should be able to check for a BOM"),
+            "partial BOM should not match"
+        );
+
+        let mut buf = vec![0; 2];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 2 bytes");
+        assert_eq!(vec![0xEF, 0xBB], buf, "stream should be unchanged");
+    }
+
+    #[test]
+    fn test_transaction_commits_on_ok() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![0, 1, 2, 3]));
+        let result: std::io::Result<Vec<u8>> = reader.transaction(|reader| {
+            let mut buf = vec![0; 2];
+            reader.read_exact(&mut buf)?;
+            Ok(buf)
+        });
+        assert_eq!(vec![0, 1], result.expect("transaction should succeed"));
+        assert!(!reader.is_marked(), "reader should not be left marked");
+
+        let mut buf = vec![0; 2];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 2 bytes");
+        assert_eq!(
+            vec![2, 3],
+            buf,
+            "stream should advance past the transaction"
+        );
+    }
+
+    #[test]
+    fn test_transaction_rewinds_on_err() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![0, 1, 2, 3]));
+        let result: Result<(), &str> = reader.transaction(|reader| {
+            let mut buf = vec![0; 3];
+            reader
+                .read_exact(&mut buf)
+                .expect("should be able to read 3 bytes");
+            Err("not a match")
+        });
+        assert_eq!(Err("not a match"), result, "error should be returned");
+        assert!(!reader.is_marked(), "reader should not be left marked");
+
+        let mut buf = vec![0; 4];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 4 bytes");
+        assert_eq!(
+            vec![0, 1, 2, 3],
+            buf,
+            "next read should see the original bytes"
+        );
+    }
+
+    #[test]
+    fn test_nested_transactions() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![0, 1, 2, 3]));
+        let result: Result<Vec<u8>, ()> = reader.transaction(|reader| {
+            let mut first = vec![0; 1];
+            reader
+                .read_exact(&mut first)
+                .expect("should be able to read 1 byte");
+            let inner: Result<(), ()> = reader.transaction(|reader| {
+                let mut skipped = vec![0; 2];
+                reader
+                    .read_exact(&mut skipped)
+                    .expect("should be able to read 2 bytes");
+                Err(())
+            });
+            assert!(inner.is_err(), "inner transaction should fail");
+
+            let mut second = vec![0; 1];
+            reader
+                .read_exact(&mut second)
+                .expect("should be able to read 1 byte");
+            first.extend(second);
+            Ok(first)
+        });
+        assert_eq!(
+            vec![0, 1],
+            result.expect("outer transaction should succeed"),
+            "inner rollback should not affect the outer transaction"
+        );
+    }
+
+    #[test]
+    fn test_mark_matches_buffered_reader() {
+        let input_data: Vec<u8> = (0..16).collect();
+        let mut reader = MarkableReader::new(Cursor::new(input_data.clone()));
+        let mut buffered_reader = BufferedMarkableReader::new(Cursor::new(input_data.clone()));
+
+        let mut buf = vec![0; 6];
+        let mut buffered_buf = vec![0; 6];
+        assert_eq!(
+            reader.mark(),
+            buffered_reader.mark(),
+            "first mark should match"
+        );
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 6 bytes");
+        buffered_reader
+            .read_exact(&mut buffered_buf)
+            .expect("should be able to read 6 bytes");
+        reader.reset();
+        buffered_reader.reset();
+
+        let mut partial = vec![0; 2];
+        let mut buffered_partial = vec![0; 2];
+        reader
+            .read_exact(&mut partial)
+            .expect("should be able to read 2 bytes");
+        buffered_reader
+            .read_exact(&mut buffered_partial)
+            .expect("should be able to read 2 bytes");
+
+        let discarded = reader.mark();
+        assert_eq!(
+            discarded,
+            buffered_reader.mark(),
+            "re-mark should discard the same"
+        );
+        assert_eq!(2, discarded, "only the bytes read should be discarded");
+
+        let mut rest = vec![0; 10];
+        let mut buffered_rest = vec![0; 10];
+        reader
+            .read_exact(&mut rest)
+            .expect("should be able to read 10 bytes");
+        buffered_reader
+            .read_exact(&mut buffered_rest)
+            .expect("should be able to read 10 bytes");
+        assert_eq!(&input_data[2..12], rest, "unread replay should be kept");
+        assert_eq!(rest, buffered_rest, "post-mark reads should match");
+
+        reader.reset();
+        buffered_reader.reset();
+        reader
+            .read_exact(&mut rest)
+            .expect("should be able to replay 10 bytes");
+        buffered_reader
+            .read_exact(&mut buffered_rest)
+            .expect("should be able to replay 10 bytes");
+        assert_eq!(rest, buffered_rest, "replays should match");
+    }
+
+    #[test]
+    fn test_unread() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![0, 1, 2, 3]));
+        let mut buf = vec![0; 3];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 3 bytes");
+        reader
+            .unread(&[7, 8])
+            .expect("should be able to push back 2 bytes");
+
+        let mut buf = vec![0; 3];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 3 bytes");
+        assert_eq!(vec![7, 8, 3], buf, "pushed back bytes should be read first");
+    }
+
+    #[test]
+    fn test_unread_while_marked() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![0, 1, 2, 3]));
+        let mut buf = vec![0; 2];
+        reader.mark();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 2 bytes");
+        reader
+            .unread(&[1])
+            .expect("should be able to push back 1 byte");
+
+        let mut buf = vec![0; 3];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 3 bytes");
+        assert_eq!(vec![1, 2, 3], buf, "pushed back byte should be read first");
+
+        reader.reset();
+        let mut buf = vec![0; 5];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to replay 5 bytes");
+        assert_eq!(
+            vec![0, 1, 1, 2, 3],
+            buf,
+            "pushed back byte should be replayed"
+        );
+    }
+
+    #[test]
+    fn test_unread_exceeding_limit() {
+        let mut reader =
+            MarkableReader::new_with_limited_back_buffer(Cursor::new(vec![0, 1, 2, 3]), 3);
+        let mut buf = vec![0; 2];
+        reader.mark();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 2 bytes");
+        assert_eq!(
+            std::io::ErrorKind::OutOfMemory,
+            reader.unread(&[5, 6]).unwrap_err().kind(),
+            "pushed back bytes should not exceed the limit"
+        );
+
+        let mut single_byte = [0; 1];
+        reader
+            .read_exact(&mut single_byte)
+            .expect("should be able to read 1 byte");
+        assert_eq!([2], single_byte, "stream should be unchanged");
+    }
+
+    #[test]
+    fn test_fill_to() {
+        let input_data: Vec<u8> = (0..2 * DEFAULT_BUFFER_SIZE).map(|i| i as u8).collect();
+        let (head, tail) = input_data.split_at(DEFAULT_BUFFER_SIZE / 2);
+        let mut reader =
+            MarkableReader::new(Cursor::new(head.to_vec()).chain(Cursor::new(tail.to_vec())));
+
+        let target = DEFAULT_BUFFER_SIZE + 3;
+        let available = reader.fill_to(target).expect("should be able to fill");
+        assert!(
+            available >= target,
+            "at least {} bytes should be buffered",
+            target
+        );
+
+        let mut buf = vec![0; input_data.len()];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read all bytes");
+        assert_eq!(input_data, buf, "filled bytes should not be consumed");
+    }
+
+    #[test]
+    fn test_fill_to_past_eof() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![0, 1, 2]));
+        let mut single_byte = [0; 1];
+        reader.mark();
+        reader
+            .read_exact(&mut single_byte)
+            .expect("should be able to read 1 byte");
+        reader.reset();
+
+        assert_eq!(
+            3,
+            reader.fill_to(10).expect("should be able to fill"),
+            "should stop at eof"
+        );
+        let mut buf = vec![0; 3];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 3 bytes");
+        assert_eq!(vec![0, 1, 2], buf, "filled bytes should include the replay");
+    }
+
+    #[test]
+    fn test_rewind_replays_marked_region() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![0, 1, 2, 3]));
+        let mut buf = vec![0; 2];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 2 bytes");
+        reader.mark();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 2 bytes");
+        reader.rewind();
+
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to replay 2 bytes");
+        assert_eq!(vec![2, 3], buf, "rewind should return to the mark");
+    }
+
+    #[test]
+    fn test_rewind_to_start() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![0, 1, 2, 3]));
+        let mut buf = vec![0; 2];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 2 bytes");
+        reader.mark();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 2 bytes");
+        reader
+            .rewind_to_start()
+            .expect("should be able to rewind to the start");
+        assert!(!reader.is_marked(), "mark should be dropped");
+        assert_eq!(0, reader.position(), "position should return to the start");
+
+        let mut buf = vec![0; 4];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 4 bytes");
+        assert_eq!(
+            vec![0, 1, 2, 3],
+            buf,
+            "stream should be read from the start"
+        );
+    }
+
+    #[test]
+    fn test_buffered_bytes() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![0, 1, 2, 3, 4, 5]));
+        assert!(
+            reader.buffered_bytes().is_empty(),
+            "nothing should be buffered yet"
+        );
+
+        let mut buf = vec![0; 3];
+        reader.mark();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 3 bytes");
+        reader.reset();
+        assert!(
+            reader.buffered_bytes().starts_with(&[0, 1, 2]),
+            "replayable region should be buffered"
+        );
+        assert_eq!(0, reader.position(), "position should not advance");
+
+        let mut single_byte = [0; 1];
+        reader
+            .read_exact(&mut single_byte)
+            .expect("should be able to read 1 byte");
+        assert!(
+            reader.buffered_bytes().starts_with(&[1, 2]),
+            "read bytes should no longer be buffered"
+        );
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 3 bytes");
+        assert_eq!(vec![1, 2, 3], buf, "buffered bytes should be read in order");
+    }
+
+    #[test]
+    fn test_reserve_avoids_reallocation() {
+        let input_data = vec![9; 64 * 1024];
+        let mut reader = MarkableReader::new(Cursor::new(input_data.clone()));
+        let mut buf = vec![0; 16];
+        reader.mark();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 16 bytes");
+        reader.reserve(input_data.len() - buf.len());
+        let capacity = reader.mark_buffer.capacity();
+        assert!(
+            capacity >= input_data.len(),
+            "capacity should cover the reservation"
+        );
+
+        let mut rest = vec![0; input_data.len() - buf.len()];
+        reader
+            .read_exact(&mut rest)
+            .expect("should be able to read the rest");
+        assert_eq!(
+            capacity,
+            reader.mark_buffer.capacity(),
+            "mark buffer should not grow"
+        );
+
+        reader.reset();
+        let mut replayed = vec![0; input_data.len()];
+        reader
+            .read_exact(&mut replayed)
+            .expect("should be able to replay");
+        assert_eq!(
+            input_data, replayed,
+            "reserving should keep the marked bytes"
+        );
+    }
+
+    #[test]
+    fn test_reserve_capped_by_limit() {
+        let mut reader =
+            MarkableReader::new_with_capacity_and_limit(Cursor::new(vec![0; 8]), 16, 100);
+        reader.reserve(1000);
+        let capacity = reader.mark_buffer.capacity();
+        assert!(capacity >= 100, "capacity should grow to the limit");
+        assert!(capacity < 1000, "capacity should not grow past the limit");
+    }
+
+    #[test]
+    fn test_total_discarded() {
+        let mut reader = MarkableReader::new(Cursor::new((0..32).collect::<Vec<u8>>()));
+        let mut expected = reader.mark() as u64;
+        for length in [3, 5, 7] {
+            let mut buf = vec![0; length];
+            reader.read_exact(&mut buf).expect("should be able to read");
+            expected += reader.mark() as u64;
+        }
+        assert_eq!(
+            15, expected,
+            "each mark should discard the bytes read before it"
+        );
+        assert_eq!(
+            expected,
+            reader.total_discarded(),
+            "discards should accumulate"
+        );
+
+        let mut buf = vec![0; 4];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 4 bytes");
+        reader.clear_buffer();
+        assert_eq!(
+            expected + 4,
+            reader.total_discarded(),
+            "clearing should be counted"
+        );
+    }
+
+    #[test]
+    fn test_take_markable_limits_reads() {
+        let mut reader = MarkableReader::new(Cursor::new((0..10).collect::<Vec<u8>>()));
+        let mut single_byte = [0; 1];
+        reader
+            .read_exact(&mut single_byte)
+            .expect("should be able to read 1 byte");
+
+        let mut limited = reader.take_markable(4);
+        let mut output = Vec::new();
+        limited
+            .read_to_end(&mut output)
+            .expect("should be able to read to the limit");
+        assert_eq!(vec![1, 2, 3, 4], output, "reads should stop at the limit");
+        assert_eq!(0, limited.limit(), "limit should be used up");
+
+        let mut reader = limited.into_inner();
+        reader
+            .read_exact(&mut single_byte)
+            .expect("should be able to read 1 byte");
+        assert_eq!([5], single_byte, "reader should continue after the limit");
+    }
+
+    #[test]
+    fn test_take_markable_unbounded_limit() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![0, 1, 2, 3]));
+        let mut single_byte = [0; 1];
+        reader
+            .read_exact(&mut single_byte)
+            .expect("should be able to read 1 byte");
+
+        let mut limited = reader.take_markable(u64::MAX);
+        let mut output = vec![0; 3];
+        limited
+            .read_exact(&mut output)
+            .expect("should be able to read the rest");
+        assert_eq!(vec![1, 2, 3], output, "a huge limit should not stop reads");
+    }
+
+    #[test]
+    fn test_take_markable_reset_within_limit() {
+        let reader = MarkableReader::new(Cursor::new((0..10).collect::<Vec<u8>>()));
+        let mut limited = reader.take_markable(5);
+        let mut buf = vec![0; 3];
+        limited.mark();
+        limited
+            .read_exact(&mut buf)
+            .expect("should be able to read 3 bytes");
+        assert_eq!(2, limited.limit(), "reads should count towards the limit");
+
+        limited.reset();
+        assert_eq!(
+            5,
+            limited.limit(),
+            "replayed bytes should not be counted twice"
+        );
+        let mut output = Vec::new();
+        limited
+            .read_to_end(&mut output)
+            .expect("should be able to read to the limit");
+        assert_eq!(
+            vec![0, 1, 2, 3, 4],
+            output,
+            "replay should continue to the limit"
+        );
+        assert_eq!(
+            0,
+            limited
+                .read(&mut buf)
+                .expect("should be able to read at the limit"),
+            "limit should be enforced"
+        );
+    }
+
+    #[test]
+    fn test_chain_markable_across_seam() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![0, 1, 2]));
+        let mut single_byte = [0; 1];
+        reader
+            .read_exact(&mut single_byte)
+            .expect("should be able to read 1 byte");
+        reader.mark();
+
+        let mut chained = reader.chain_markable(Cursor::new(vec![3, 4, 5]));
+        let mut buf = vec![0; 4];
+        chained
+            .read_exact(&mut buf)
+            .expect("should be able to read across the seam");
+        assert_eq!(
+            vec![1, 2, 3, 4],
+            buf,
+            "reads should continue into the next stream"
+        );
+
+        chained.reset();
+        chained
+            .read_exact(&mut buf)
+            .expect("should be able to replay across the seam");
+        assert_eq!(
+            vec![1, 2, 3, 4],
+            buf,
+            "reset should rewind into the first stream"
+        );
+
+        let mut rest = [0; 1];
+        chained
+            .read_exact(&mut rest)
+            .expect("should be able to read the rest");
+        assert_eq!([5], rest, "the next stream should not be re-read");
+    }
+
+    #[test]
+    fn test_chain_markable_keeps_buffered_bytes() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![0, 1]));
+        reader.peek(2).expect("should be able to peek 2 bytes");
+        let mut chained = reader.chain_markable(Cursor::new(vec![2]));
+        let mut buf = vec![0; 3];
+        chained
+            .read_exact(&mut buf)
+            .expect("should be able to read 3 bytes");
+        assert_eq!(vec![0, 1, 2], buf, "peeked bytes should carry over");
+    }
+
+    #[test]
+    fn test_mut_ref_passed_as_impl_read() {
+        fn read_two(mut reader: impl Read) -> Vec<u8> {
+            let mut buf = vec![0; 2];
+            reader
+                .read_exact(&mut buf)
+                .expect("should be able to read 2 bytes");
+            buf
+        }
+
+        let mut reader = MarkableReader::new(Cursor::new(vec![0, 1, 2, 3]));
+        assert_eq!(
+            vec![0, 1],
+            read_two(&mut reader),
+            "reads should go through the reference"
+        );
+
+        reader.mark();
+        assert_eq!(
+            vec![2, 3],
+            read_two(&mut reader),
+            "the reference should see marked reads"
+        );
+        reader.reset();
+        assert_eq!(
+            vec![2, 3],
+            read_two(&mut reader),
+            "marked bytes should replay after reset"
+        );
+    }
+
+    #[test]
+    fn test_remaining_limit_bounded() {
+        let mut reader = MarkableReader::new_with_limited_back_buffer(Cursor::new(vec![0; 16]), 8);
+        assert_eq!(
+            Some(8),
+            reader.remaining_limit(),
+            "unmarked headroom should be the limit"
+        );
+
+        reader.mark();
+        let mut buf = vec![0; 3];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 3 bytes");
+        assert_eq!(
+            Some(5),
+            reader.remaining_limit(),
+            "marked reads should use headroom"
+        );
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 3 bytes");
+        assert_eq!(
+            Some(2),
+            reader.remaining_limit(),
+            "headroom should keep shrinking"
+        );
+
+        reader.reset();
+        reader.mark();
+        assert_eq!(
+            Some(2),
+            reader.remaining_limit(),
+            "replayed bytes should stay counted"
+        );
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to replay 3 bytes");
+        assert_eq!(
+            Some(2),
+            reader.remaining_limit(),
+            "replaying should not use headroom"
+        );
+        reader.mark();
+        assert_eq!(
+            Some(5),
+            reader.remaining_limit(),
+            "re-marking should free replayed bytes"
+        );
+    }
+
+    #[test]
+    fn test_remaining_limit_unbounded() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![0; 4]));
+        reader.mark();
+        let mut buf = vec![0; 4];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 4 bytes");
+        assert_eq!(
+            None,
+            reader.remaining_limit(),
+            "an unbounded reader has no limit"
+        );
+    }
+
+    #[test]
+    fn test_try_read_marked_limit_exceeded() {
+        let mut reader = MarkableReader::new_with_limited_back_buffer(Cursor::new(vec![0; 16]), 4);
+        reader.mark();
+        let mut buf = vec![0; 3];
+        assert_eq!(
+            3,
+            reader
+                .try_read_marked(&mut buf)
+                .expect("should be able to read 3 bytes")
+        );
+
+        match reader.try_read_marked(&mut buf) {
+            Err(MarkBufferError::LimitExceeded { used, limit }) => {
+                assert_eq!(6, used, "used should count the bytes the read needed");
+                assert_eq!(4, limit, "limit should be the mark buffer limit");
+            }
+            other => panic!("expected a limit error, got {other:?}"),
+        }
+
+        let err = reader.read(&mut buf).expect_err("read should still fail");
+        assert_eq!(
+            std::io::ErrorKind::OutOfMemory,
+            err.kind(),
+            "read should report the limit as out of memory"
+        );
+    }
+
+    #[test]
+    fn test_try_read_marked_passes_io_errors() {
+        struct FailingReader;
+
+        impl Read for FailingReader {
+            fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::from(std::io::ErrorKind::BrokenPipe))
+            }
+        }
+
+        let mut reader = MarkableReader::new(FailingReader);
+        let mut buf = vec![0; 4];
+        match reader.try_read_marked(&mut buf) {
+            Err(MarkBufferError::Io(err)) => assert_eq!(
+                std::io::ErrorKind::BrokenPipe,
+                err.kind(),
+                "inner errors should be passed through"
+            ),
+            other => panic!("expected an io error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_on_limit_grows_limit() {
+        let mut reader = MarkableReader::new_with_limited_back_buffer(Cursor::new(vec![0; 16]), 4);
+        let calls = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded_calls = calls.clone();
+        reader.on_limit(move |used, limit| {
+            recorded_calls.lock().unwrap().push((used, limit));
+            Some(limit * 2)
+        });
+
+        reader.mark();
+        let mut buf = vec![0; 6];
+        reader
+            .read_exact(&mut buf)
+            .expect("the callback should let the read succeed");
+        assert_eq!(
+            vec![(6, 4)],
+            *calls.lock().unwrap(),
+            "callback should see used and limit"
+        );
+        assert_eq!(
+            Some(2),
+            reader.remaining_limit(),
+            "the limit should have been raised"
+        );
+
+        reader.reset();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to replay 6 bytes");
+        assert_eq!(vec![0; 6], buf, "the grown region should replay");
+    }
+
+    #[test]
+    fn test_on_limit_declines() {
+        let mut reader = MarkableReader::new_with_limited_back_buffer(Cursor::new(vec![0; 16]), 4);
+        reader.on_limit(|_, _| None);
+        reader.mark();
+        let mut buf = vec![0; 6];
+        let err = reader
+            .read_exact(&mut buf)
+            .expect_err("the read should still fail");
+        assert_eq!(
+            std::io::ErrorKind::OutOfMemory,
+            err.kind(),
+            "declining should leave the limit error in place"
+        );
+    }
+
+    #[test]
+    fn test_growable_limit() {
+        let input_data: Vec<u8> = (0..32).collect();
+        let mut reader =
+            MarkableReader::new_with_growable_limit(Cursor::new(input_data.clone()), 4, 16);
+        reader.mark();
+        let mut buf = vec![0; 6];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read past the initial limit");
+        assert_eq!(
+            Some(2),
+            reader.remaining_limit(),
+            "the limit should have doubled to 8"
+        );
+
+        let mut rest = vec![0; 10];
+        reader
+            .read_exact(&mut rest)
+            .expect("should be able to grow up to the max");
+        assert_eq!(
+            Some(0),
+            reader.remaining_limit(),
+            "the limit should be capped at the max"
+        );
+
+        reader.reset();
+        let mut replayed = vec![0; 16];
+        reader
+            .read_exact(&mut replayed)
+            .expect("should be able to replay 16 bytes");
+        assert_eq!(
+            input_data[..16],
+            replayed,
+            "growing should preserve the marked bytes"
+        );
+    }
+
+    #[test]
+    fn test_growable_limit_exceeding_max() {
+        let mut reader = MarkableReader::new_with_growable_limit(Cursor::new(vec![0; 32]), 4, 16);
+        reader.mark();
+        let mut buf = vec![0; 17];
+        let err = reader
+            .read_exact(&mut buf)
+            .expect_err("should not grow past the max");
+        assert_eq!(
+            std::io::ErrorKind::OutOfMemory,
+            err.kind(),
+            "exceeding the max should be out of memory"
+        );
+    }
+
+    #[test]
+    fn test_is_eof() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![0, 1, 2]));
+        assert!(
+            !reader.is_eof().expect("should be able to check for eof"),
+            "bytes remain"
+        );
+
+        reader.mark();
+        let mut buf = vec![0; 3];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 3 bytes");
+        assert!(
+            reader.is_eof().expect("should be able to check for eof"),
+            "all bytes read"
+        );
+
+        reader.reset();
+        assert!(
+            !reader.is_eof().expect("should be able to check for eof"),
+            "bytes pending replay should not be eof"
+        );
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to replay 3 bytes");
+        assert_eq!(
+            vec![0, 1, 2],
+            buf,
+            "checking for eof should not consume bytes"
+        );
+        assert!(
+            reader.is_eof().expect("should be able to check for eof"),
+            "replay finished"
+        );
+    }
+
+    #[test]
+    fn test_reset_keep_mark() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![0, 1, 2, 3, 4, 5]));
+        reader.mark();
+        let mut buf = vec![0; 3];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 3 bytes");
+
+        reader.reset_keep_mark();
+        assert!(reader.is_marked(), "the stream should stay marked");
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to replay 3 bytes");
+        assert_eq!(
+            vec![0, 1, 2],
+            buf,
+            "the first pass should replay the region"
+        );
+
+        reader.reset_keep_mark();
+        let mut longer = vec![0; 4];
+        reader
+            .read_exact(&mut longer)
+            .expect("should be able to replay and read on");
+        assert_eq!(
+            vec![0, 1, 2, 3],
+            longer,
+            "the second pass should replay the region"
+        );
+
+        reader.reset();
+        reader
+            .read_exact(&mut longer)
+            .expect("should be able to replay 4 bytes");
+        assert_eq!(
+            vec![0, 1, 2, 3],
+            longer,
+            "a final reset should replay everything read"
+        );
+        assert_eq!(4, reader.position(), "position should follow the replays");
+    }
+
+    #[test]
+    fn test_seek_through_generic_bound() {
+        fn skip_header<S: Read + Seek>(stream: &mut S) -> u64 {
+            stream
+                .seek(SeekFrom::Start(2))
+                .expect("should be able to seek")
+        }
+
+        let mut reader = MarkableReader::new(Cursor::new(vec![0, 1, 2, 3]));
+        assert_eq!(
+            2,
+            skip_header(&mut reader),
+            "generic code should be able to seek"
+        );
+        let mut buf = vec![0; 2];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 2 bytes");
+        assert_eq!(vec![2, 3], buf, "should read after the skipped header");
+    }
+
+    #[test]
+    fn test_limit_errors_without_sliding_window() {
+        let mut reader = MarkableReader::new_with_limited_back_buffer(Cursor::new(vec![0; 16]), 4);
+        reader.mark();
+        let mut buf = vec![0; 3];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 3 bytes");
+        let err = reader
+            .read_exact(&mut buf)
+            .expect_err("should exceed the limit");
+        assert_eq!(
+            std::io::ErrorKind::OutOfMemory,
+            err.kind(),
+            "should be out of memory"
+        );
+    }
+
+    #[test]
+    fn test_sliding_window_invalidates_mark() {
+        let input_data: Vec<u8> = (0..16).collect();
+        let mut reader =
+            MarkableReader::new_with_limited_back_buffer(Cursor::new(input_data.clone()), 4);
+        reader.set_sliding_window(true);
+        reader.mark();
+        let mut buf = vec![0; 3];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 3 bytes");
+        assert!(
+            reader.checked_reset().is_ok(),
+            "a mark within the window should reset"
+        );
+
+        reader.mark();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 3 bytes");
+        reader
+            .read_exact(&mut buf)
+            .expect("the window should slide instead of failing");
+        assert_eq!(
+            input_data[3..6],
+            buf,
+            "reads should continue past the limit"
+        );
+
+        let err = reader
+            .checked_reset()
+            .expect_err("the mark should be invalidated");
+        assert_eq!(
+            std::io::ErrorKind::InvalidData,
+            err.kind(),
+            "should be invalid data"
+        );
+        assert!(!reader.is_marked(), "the stream should be unmarked");
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 3 bytes");
+        assert_eq!(input_data[6..9], buf, "no bytes should be replayed");
+    }
+
+    #[test]
+    fn test_sliding_window_plain_reset_does_not_replay() {
+        let input_data: Vec<u8> = (0..16).collect();
+        let mut reader =
+            MarkableReader::new_with_limited_back_buffer(Cursor::new(input_data.clone()), 4);
+        reader.set_sliding_window(true);
+        reader.mark();
+        let mut buf = vec![0; 6];
+        reader
+            .read_exact(&mut buf)
+            .expect("the window should slide instead of failing");
+        reader.reset();
+        assert_eq!(6, reader.position(), "position should not move back");
+
+        reader.mark();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 6 bytes");
+        assert_eq!(
+            input_data[6..12],
+            buf,
+            "a new mark should start a fresh window"
+        );
+    }
+
+    #[test]
+    fn test_read_exact_or_n() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![0, 1, 2, 3, 4]));
+        let mut buf = vec![0; 3];
+        assert_eq!(
+            3,
+            reader.read_exact_or_n(&mut buf).unwrap(),
+            "should fill the buffer"
+        );
+        assert_eq!(vec![0, 1, 2], buf, "should read the first bytes");
+
+        buf.fill(9);
+        assert_eq!(
+            2,
+            reader.read_exact_or_n(&mut buf).unwrap(),
+            "should report a short read"
+        );
+        assert_eq!(vec![3, 4, 9], buf, "should fill what remains of the stream");
+        assert_eq!(
+            0,
+            reader.read_exact_or_n(&mut buf).unwrap(),
+            "should report the end"
+        );
+    }
+
+    #[test]
+    fn test_boxed_marker_streams() {
+        let mut streams: Vec<BoxedMarkerStream> = vec![
+            Box::new(MarkableReader::new(Cursor::new(vec![0, 1]))),
+            Box::new(BufferedMarkableReader::new(Cursor::new(vec![0, 1]))),
+        ];
+
+        for stream in streams.iter_mut() {
+            assert_eq!(
+                0,
+                stream.mark(),
+                "nothing should be discarded by the first mark"
+            );
+            assert!(stream.is_marked(), "the boxed stream should be marked");
+            stream.reset();
+            assert!(!stream.is_marked(), "the boxed stream should be reset");
+        }
+    }
+
+    #[test]
+    fn test_with_prefix() {
+        let mut reader = MarkableReader::with_prefix(Cursor::new(vec![3, 4, 5]), vec![0, 1, 2]);
+        let mut single_byte = [0; 1];
+        reader
+            .read_exact(&mut single_byte)
+            .expect("should be able to read 1 byte");
+        assert_eq!([0], single_byte, "the prefix should be read first");
+
+        reader.mark();
+        let mut buf = vec![0; 4];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read across the prefix");
+        assert_eq!(
+            vec![1, 2, 3, 4],
+            buf,
+            "the inner bytes should follow the prefix"
+        );
+
+        reader.reset();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to replay 4 bytes");
+        assert_eq!(
+            vec![1, 2, 3, 4],
+            buf,
+            "reset should replay the prefix and inner bytes"
+        );
+        assert_eq!(5, reader.position(), "position should count the prefix");
+    }
+
+    #[test]
+    fn test_find() {
+        let mut reader = MarkableReader::new(Cursor::new(b"key: value\r\nnext".to_vec()));
+        assert_eq!(
+            Some(3),
+            reader.find(b": ", 16).unwrap(),
+            "should find the separator"
+        );
+        assert_eq!(
+            Some(10),
+            reader.find(b"\r\n", 16).unwrap(),
+            "should find the line end"
+        );
+        assert_eq!(
+            None,
+            reader.find(b"\r\n", 11).unwrap(),
+            "a partial match should not count"
+        );
+        assert_eq!(
+            None,
+            reader.find(b"missing", 64).unwrap(),
+            "should not find a missing needle"
+        );
+
+        let mut buf = vec![0; 3];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 3 bytes");
+        assert_eq!(b"key".to_vec(), buf, "finding should not consume bytes");
+    }
+
+    #[test]
+    fn test_find_across_refill() {
+        let mut input_data = vec![b'.'; 14];
+        input_data.extend(b"END");
+        let mut reader = MarkableReader::new(Cursor::new(input_data));
+        let mut buf = vec![0; 4];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 4 bytes");
+        reader.peek(4).expect("should be able to peek");
+
+        assert_eq!(
+            Some(10),
+            reader.find(b"END", 32).unwrap(),
+            "should find past the peeked bytes"
+        );
+        reader.mark();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 4 bytes");
+        reader.reset();
+        assert_eq!(
+            Some(10),
+            reader.find(b"END", 32).unwrap(),
+            "should find past replayed bytes"
+        );
+    }
+
+    #[test]
+    fn test_rewind_by() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![0, 1, 2, 3, 4, 5]));
+        reader.mark();
+        let mut buf = vec![0; 4];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 4 bytes");
+
+        assert_eq!(1, reader.rewind_by(1).unwrap(), "should rewind 1 byte");
+        assert!(reader.is_marked(), "the stream should stay marked");
+        assert_eq!(3, reader.position(), "position should move back");
+        let mut pair = vec![0; 2];
+        reader
+            .read_exact(&mut pair)
+            .expect("should be able to read 2 bytes");
+        assert_eq!(vec![3, 4], pair, "the rewound byte should be read again");
+
+        assert_eq!(
+            5,
+            reader.rewind_by(10).unwrap(),
+            "should clamp to the marked bytes"
+        );
+        reader
+            .read_exact(&mut pair)
+            .expect("should be able to read 2 bytes");
+        assert_eq!(vec![0, 1], pair, "should replay from the mark");
+    }
+
+    #[test]
+    fn test_rewind_by_unmarked() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![0, 1]));
+        let err = reader
+            .rewind_by(1)
+            .expect_err("an unmarked stream cannot rewind");
+        assert_eq!(
+            std::io::ErrorKind::InvalidInput,
+            err.kind(),
+            "should be invalid input"
+        );
+    }
+
+    #[test]
+    fn test_configuration_getters() {
+        let reader = MarkableReader::new(Cursor::new(vec![0]));
+        assert_eq!(None, reader.mark_limit(), "new should be unbounded");
+        assert!(
+            reader.mark_capacity() >= DEFAULT_MARKER_BUFFER_SIZE,
+            "new should use the default capacity"
+        );
+
+        let reader = MarkableReader::new_with_limited_back_buffer(Cursor::new(vec![0]), 64);
+        assert_eq!(Some(64), reader.mark_limit(), "should report the limit");
+
+        let reader = MarkableReader::new_with_capacity_and_limit(Cursor::new(vec![0]), 32, 128);
+        assert_eq!(Some(128), reader.mark_limit(), "should report the limit");
+        assert!(reader.mark_capacity() >= 32, "should report the capacity");
+
+        let reader = MarkableReaderBuilder::new()
+            .mark_capacity(16)
+            .mark_limit(256)
+            .build(Cursor::new(vec![0]));
+        assert_eq!(
+            Some(256),
+            reader.mark_limit(),
+            "should report the built limit"
+        );
+        assert!(
+            reader.mark_capacity() >= 16,
+            "should report the built capacity"
+        );
+    }
+
+    #[test]
+    fn test_records() {
+        let mut reader = MarkableReader::new(Cursor::new(b"a,bc,,def,".to_vec()));
+        let records: Vec<Vec<u8>> = reader
+            .records(b',')
+            .collect::<std::io::Result<_>>()
+            .expect("should be able to read the records");
+        assert_eq!(
+            vec![b"a".to_vec(), b"bc".to_vec(), vec![], b"def".to_vec()],
+            records,
+            "should split on the delimiter"
+        );
+    }
+
+    #[test]
+    fn test_records_without_trailing_delimiter() {
+        let mut reader = MarkableReader::new(Cursor::new(b"one\ntwo\nthree".to_vec()));
+        reader.mark();
+        let mut records = reader.records(b'\n');
+        assert_eq!(
+            b"one".to_vec(),
+            records.next().unwrap().unwrap(),
+            "should read a record"
+        );
+        assert_eq!(
+            b"two".to_vec(),
+            records.next().unwrap().unwrap(),
+            "should read a record"
+        );
+        assert_eq!(
+            b"three".to_vec(),
+            records.next().unwrap().unwrap(),
+            "should read the tail"
+        );
+        assert!(records.next().is_none(), "should end after the last record");
+
+        reader.reset();
+        let first = reader.records(b'\n').next().unwrap().unwrap();
+        assert_eq!(b"one".to_vec(), first, "marked records should replay");
+    }
+
+    #[test]
+    fn test_checked_read() {
+        let mut reader = MarkableReader::new_with_limited_back_buffer(Cursor::new(vec![0; 8]), 4);
+        reader.mark();
+        let mut buf = vec![0; 3];
+        assert_eq!(
+            3,
+            reader.checked_read(&mut buf).unwrap(),
+            "should read 3 bytes"
+        );
+        match reader.checked_read(&mut buf) {
+            Err(ReadError::LimitExceeded { used, limit }) => {
+                assert_eq!((6, 4), (used, limit), "should carry the sizes");
+            }
+            other => panic!("expected a limit error, got {other:?}"),
+        }
+
+        reader.clear_buffer();
+        let mut rest = vec![0; 8];
+        reader
+            .read_exact_or_n(&mut rest)
+            .expect("should read the remaining bytes");
+        match reader.checked_read(&mut rest) {
+            Err(ReadError::Eof) => {}
+            other => panic!("expected the end of the stream, got {other:?}"),
+        }
+        assert_eq!(
+            0,
+            reader.checked_read(&mut []).unwrap(),
+            "an empty read is not eof"
+        );
+    }
+
+    #[test]
+    fn test_checked_read_io_error() {
+        struct FailingReader;
+
+        impl Read for FailingReader {
+            fn read(&mut self, _buf: &mut [u8]) -> std::io::Result<usize> {
+                Err(std::io::Error::from(std::io::ErrorKind::BrokenPipe))
+            }
+        }
+
+        let mut reader = MarkableReader::new(FailingReader);
+        match reader.checked_read(&mut [0; 4]) {
+            Err(ReadError::Io(err)) => {
+                assert_eq!(
+                    std::io::ErrorKind::BrokenPipe,
+                    err.kind(),
+                    "should pass the error"
+                )
+            }
+            other => panic!("expected an io error, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn test_snapshot_restore() {
+        let input_data: Vec<u8> = (0..16).collect();
+        let mut reader = MarkableReader::new(Cursor::new(input_data.clone()));
+        let mut buf = vec![0; 4];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 4 bytes");
+        reader.peek(8).expect("should be able to peek 8 bytes");
+
+        let first = reader.snapshot();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 4 bytes");
+        let second = reader.snapshot();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 4 bytes");
+
+        reader.restore(first);
+        assert_eq!(4, reader.position(), "position should roll back");
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 4 bytes");
+        assert_eq!(input_data[4..8], buf, "buffered bytes should be read again");
+
+        reader.restore(second.clone());
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 4 bytes");
+        assert_eq!(input_data[8..12], buf, "snapshots should be independent");
+
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 4 bytes");
+        reader
+            .get_mut()
+            .seek(SeekFrom::Start(second.inner_offset()))
+            .expect("should be able to reposition the inner reader");
+        reader.restore(second);
+        let mut rest = vec![0; 8];
+        reader
+            .read_exact(&mut rest)
+            .expect("should be able to read 8 bytes");
+        assert_eq!(
+            input_data[8..],
+            rest,
+            "a repositioned inner reader should line up"
+        );
+    }
+
+    #[test]
+    fn test_send_and_sync() {
+        fn assert_send<T: Send>() {}
+        fn assert_sync<T: Sync>() {}
+
+        assert_send::<MarkableReader<Cursor<Vec<u8>>>>();
+        assert_sync::<MarkableReader<Cursor<Vec<u8>>>>();
+    }
+
+    #[test]
+    fn test_drain_buffered() {
+        let mut reader = MarkableReader::new(Cursor::new((0..16).collect::<Vec<u8>>()));
+        let mut buf = vec![0; 32];
+        assert_eq!(
+            0,
+            reader.drain_buffered(&mut buf),
+            "nothing should be buffered yet"
+        );
+
+        reader.mark();
+        let mut first = vec![0; 2];
+        reader
+            .read_exact(&mut first)
+            .expect("should be able to read 2 bytes");
+        reader.reset();
+        reader.peek(4).expect("should be able to peek");
+        let buffered = reader.buffered_bytes().len();
+
+        let drained = reader.drain_buffered(&mut buf[..3]);
+        assert_eq!(3, drained, "should fill the slice from the buffers");
+        assert_eq!(vec![0, 1, 2], buf[..3], "should drain in stream order");
+        assert_eq!(3, reader.position(), "position should advance");
+
+        let drained = reader.drain_buffered(&mut buf);
+        assert_eq!(
+            buffered - 3,
+            drained,
+            "should stop at the end of the buffers"
+        );
+        assert_eq!(
+            0,
+            reader.drain_buffered(&mut buf),
+            "the buffers should be empty"
+        );
+        assert_eq!(
+            buffered as u64,
+            reader.position(),
+            "position should follow the drain"
+        );
+    }
+
+    #[test]
+    fn test_drain_buffered_while_marked() {
+        let mut reader = MarkableReader::new_with_limited_back_buffer(Cursor::new(vec![7; 16]), 4);
+        reader.peek(8).expect("should be able to peek 8 bytes");
+        reader.mark();
+        let mut buf = vec![0; 8];
+        assert_eq!(
+            4,
+            reader.drain_buffered(&mut buf),
+            "should stop at the mark limit"
+        );
+
+        reader.reset();
+        assert_eq!(0, reader.position(), "drained bytes should be replayable");
+    }
+
+    #[test]
+    fn test_zero_length_reads() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![0, 1, 2]));
+        assert_eq!(
+            0,
+            reader.read(&mut []).unwrap(),
+            "an unmarked empty read should be Ok(0)"
+        );
+        reader.mark();
+        assert_eq!(
+            0,
+            reader.read(&mut []).unwrap(),
+            "a marked empty read should be Ok(0)"
+        );
+        assert_eq!(
+            0,
+            reader
+                .read_vectored(&mut [std::io::IoSliceMut::new(&mut [])])
+                .unwrap(),
+            "an empty vectored read should be Ok(0)"
+        );
+        assert_eq!(
+            0,
+            reader.position(),
+            "empty reads should not move the position"
+        );
+
+        let mut buf = vec![0; 3];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 3 bytes");
+        reader.reset();
+        assert_eq!(
+            0,
+            reader.read(&mut []).unwrap(),
+            "an empty read should not consume replay"
+        );
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to replay 3 bytes");
+        assert_eq!(vec![0, 1, 2], buf, "the replay should be intact");
+        assert_eq!(
+            0,
+            reader.read(&mut []).unwrap(),
+            "an empty read at the end should be Ok(0)"
+        );
+    }
+
+    #[test]
+    fn test_read_passthrough() {
+        struct PointerRecorder {
+            inner: Cursor<Vec<u8>>,
+            targets: std::sync::Arc<std::sync::Mutex<Vec<usize>>>,
+        }
+
+        impl Read for PointerRecorder {
+            fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+                self.targets.lock().unwrap().push(buf.as_ptr() as usize);
+                self.inner.read(buf)
+            }
+        }
+
+        let input_data: Vec<u8> = (0..32).collect();
+        let targets = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let inner = PointerRecorder {
+            inner: Cursor::new(input_data.clone()),
+            targets: targets.clone(),
+        };
+        let mut reader = MarkableReader::new(inner);
+
+        let mut buf = vec![0; 8];
+        let bytes_read = reader
+            .read_passthrough(&mut buf)
+            .expect("should be able to read directly");
+        assert_eq!(8, bytes_read, "should fill the slice");
+        assert_eq!(input_data[..8], buf, "should read the first bytes");
+        assert_eq!(
+            vec![buf.as_ptr() as usize],
+            *targets.lock().unwrap(),
+            "the inner reader should write straight into the slice"
+        );
+        assert!(
+            reader.buffered_bytes().is_empty(),
+            "nothing should be buffered"
+        );
+        assert_eq!(8, reader.position(), "position should advance");
+    }
+
+    #[test]
+    fn test_read_passthrough_drains_buffered_first() {
+        let input_data: Vec<u8> = (0..16).collect();
+        let mut reader = MarkableReader::new(Cursor::new(input_data.clone()));
+        reader.peek(4).expect("should be able to peek 4 bytes");
+        let buffered = reader.buffered_bytes().len();
+
+        let mut buf = vec![0; 16];
+        let drained = reader
+            .read_passthrough(&mut buf)
+            .expect("should be able to drain the buffered bytes");
+        assert_eq!(buffered, drained, "should stop at the end of the buffers");
+        assert_eq!(
+            input_data[..drained],
+            buf[..drained],
+            "should drain in order"
+        );
+
+        let mut rest = Vec::new();
+        loop {
+            match reader
+                .read_passthrough(&mut buf)
+                .expect("should be able to read the rest")
+            {
+                0 => break,
+                bytes_read => rest.extend_from_slice(&buf[..bytes_read]),
+            }
+        }
+        assert_eq!(
+            input_data[drained..],
+            rest,
+            "should continue after the drain"
+        );
+    }
+
+    #[test]
+    fn test_read_passthrough_keeps_alignment() {
+        let input_data: Vec<u8> = (0..30).collect();
+        let data = OffsetTrackingReader {
+            inner: Cursor::new(input_data.clone()),
+            read_offsets: vec![],
+        };
+        let mut reader = MarkableReader::new(data).with_read_alignment(4);
+
+        let mut output = vec![0; 6];
+        assert_eq!(
+            4,
+            reader
+                .read_passthrough(&mut output)
+                .expect("should be able to read"),
+            "passthrough read should stop at the alignment boundary"
+        );
+        assert_eq!(
+            2,
+            reader
+                .read_passthrough(&mut output[4..6])
+                .expect("should be able to read"),
+            "passthrough read too small to reach a boundary should still be filled"
+        );
+        let mut rest = vec![0; input_data.len() - 6];
+        let mut filled = 0;
+        while filled < rest.len() {
+            filled += reader
+                .read_passthrough(&mut rest[filled..])
+                .expect("should be able to read");
+        }
+        output.extend(rest);
+        assert_eq!(
+            input_data, output,
+            "passthrough reads should not change the data"
+        );
+
+        for offset in reader.into_inner().read_offsets {
+            assert_eq!(
+                0,
+                offset % 4,
+                "inner read at offset {offset} should be aligned"
+            );
+        }
+    }
+
+    #[test]
+    fn test_read_passthrough_while_marked() {
+        let input_data: Vec<u8> = (0..8).collect();
+        let mut reader = MarkableReader::new(Cursor::new(input_data.clone()));
+        reader.mark();
+        let mut buf = vec![0; 4];
+        reader
+            .read_passthrough(&mut buf)
+            .expect("should be able to read while marked");
+        reader.reset();
+
+        let mut replayed = vec![0; 4];
+        reader
+            .read_exact(&mut replayed)
+            .expect("should be able to replay the bytes");
+        assert_eq!(buf, replayed, "marked passthrough reads should be retained");
+        assert_eq!(input_data[..4], replayed, "should replay the first bytes");
+    }
+
+    #[test]
+    fn test_into_iter() {
+        let input_data: Vec<u8> = (0..64).collect();
+        let reader = MarkableReader::new(Cursor::new(input_data.clone()));
+
+        let bytes = reader
+            .into_iter()
+            .collect::<std::io::Result<Vec<u8>>>()
+            .expect("should be able to collect the bytes");
+        assert_eq!(input_data, bytes, "should yield every byte of the stream");
+    }
+
+    #[test]
+    fn test_into_iter_mark_and_reset() {
+        let input_data = vec![1, 2, 3, 4];
+        let mut reader = MarkableReader::new(Cursor::new(input_data.clone()));
+        let mut first = [0; 1];
+        reader
+            .read_exact(&mut first)
+            .expect("should be able to read a byte");
+
+        let mut iter = reader.into_iter();
+        iter.mark();
+        assert_eq!(
+            2,
+            iter.next().unwrap().unwrap(),
+            "should continue after the read"
+        );
+        iter.reset();
+
+        let mut reader = iter.into_inner();
+        let mut rest = Vec::new();
+        for byte in reader.bytes_iter() {
+            rest.push(byte.expect("should be able to read a byte"));
+        }
+        assert_eq!(input_data[1..], rest, "should replay from the mark");
+    }
+
+    #[test]
+    fn test_read_array() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![0x89, b'P', b'N', b'G', 7]));
+        reader.mark();
+        let magic: [u8; 4] = reader
+            .read_array()
+            .expect("should be able to read a 4 byte array");
+        assert_eq!(
+            [0x89, b'P', b'N', b'G'],
+            magic,
+            "should read the first 4 bytes"
+        );
+
+        reader.reset();
+        assert_eq!(
+            [0x89, b'P'],
+            reader
+                .read_array::<2>()
+                .expect("should be able to read an array after reset"),
+            "should replay the array"
+        );
+    }
+
+    #[test]
+    fn test_read_array_truncated() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![1, 2, 3]));
+        reader.mark();
+        assert_eq!(
+            std::io::ErrorKind::UnexpectedEof,
+            reader.read_array::<4>().unwrap_err().kind(),
+            "should not be able to read 4 bytes from 3"
+        );
+
+        reader.reset();
+        assert_eq!(
+            [1, 2, 3],
+            reader
+                .read_array()
+                .expect("should be able to read the bytes after reset"),
+            "the failed read should be undone"
+        );
+    }
+
+    #[test]
+    fn test_buffer_all() {
+        let input_data: Vec<u8> = (0..=255).cycle().take(5000).collect();
+        let mut reader = MarkableReader::new(Cursor::new(input_data.clone()));
+        assert_eq!(
+            input_data.len(),
+            reader
+                .buffer_all()
+                .expect("should be able to buffer the stream"),
+            "should buffer the whole stream"
+        );
+
+        let mut first_pass = vec![0; input_data.len()];
+        reader
+            .read_exact(&mut first_pass)
+            .expect("should be able to read the first pass");
+        reader.reset_keep_mark();
+        let mut second_pass = vec![0; input_data.len()];
+        reader
+            .read_exact(&mut second_pass)
+            .expect("should be able to read the second pass");
+        reader.reset();
+        let mut last_pass = vec![0; input_data.len()];
+        reader
+            .read_exact(&mut last_pass)
+            .expect("should be able to read the last pass");
+
+        assert_eq!(input_data, first_pass, "first pass should match the source");
+        assert_eq!(first_pass, second_pass, "passes should be identical");
+        assert_eq!(first_pass, last_pass, "passes should be identical");
+        assert!(
+            reader.is_eof().unwrap(),
+            "should be at the end of the stream"
+        );
+    }
+
+    #[test]
+    fn test_buffer_all_over_limit() {
+        let mut reader = MarkableReader::new_with_limited_back_buffer(Cursor::new(vec![3; 16]), 8);
+        assert_eq!(
+            std::io::ErrorKind::OutOfMemory,
+            reader.buffer_all().unwrap_err().kind(),
+            "should not be able to buffer past the limit"
+        );
+        assert!(reader.is_marked(), "should stay marked");
+
+        reader.reset();
+        let mut buf = vec![0; 8];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read after reset");
+        assert_eq!(vec![3; 8], buf, "should return to where buffering began");
+        assert_eq!(8, reader.position(), "position should follow the replay");
+    }
+
+    #[test]
+    fn test_buffer_all_with_growable_limit() {
+        let input_data: Vec<u8> = (0..100).collect();
+        let mut reader =
+            MarkableReader::new_with_growable_limit(Cursor::new(input_data.clone()), 8, 128);
+        assert_eq!(
+            input_data.len(),
+            reader
+                .buffer_all()
+                .expect("the limit should grow to fit the stream"),
+            "should buffer the whole stream"
+        );
+
+        let mut buf = vec![0; input_data.len()];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read the buffered stream");
+        assert_eq!(input_data, buf, "should replay the whole stream");
+    }
+
+    /// Yields the bytes before `fail_at`, then fails once before yielding the rest
+    struct FailOnceReader {
+        inner: Cursor<Vec<u8>>,
+        fail_at: u64,
+        failed: bool,
+    }
+
+    impl Read for FailOnceReader {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            let position = self.inner.position();
+            if self.failed || position > self.fail_at {
+                return self.inner.read(buf);
+            }
+            if position == self.fail_at {
+                self.failed = true;
+                return Err(std::io::Error::from(std::io::ErrorKind::BrokenPipe));
+            }
+
+            let len = buf.len().min((self.fail_at - position) as usize);
+            self.inner.read(&mut buf[..len])
+        }
+    }
+
+    #[test]
+    fn test_inner_error_mid_mark() {
+        let input_data: Vec<u8> = (0..8).collect();
+        let inner = FailOnceReader {
+            inner: Cursor::new(input_data.clone()),
+            fail_at: 3,
+            failed: false,
+        };
+        let mut reader = MarkableReader::new(inner);
+        reader.mark();
+
+        let mut buf = vec![0; 8];
+        assert_eq!(
+            3,
+            reader
+                .read(&mut buf)
+                .expect("bytes read before the error should be returned"),
+            "should return the bytes before the error"
+        );
+        assert_eq!(
+            3,
+            reader.position(),
+            "only the returned bytes should be consumed"
+        );
+
+        reader.reset();
+        let mut replayed = vec![0; input_data.len()];
+        reader
+            .read_exact(&mut replayed)
+            .expect("should be able to read after reset");
+        assert_eq!(
+            input_data, replayed,
+            "reset should replay exactly the bytes that were read"
+        );
+    }
+
+    #[test]
+    fn test_inner_error_at_start_of_read() {
+        let input_data: Vec<u8> = (0..8).collect();
+        let inner = FailOnceReader {
+            inner: Cursor::new(input_data.clone()),
+            fail_at: 0,
+            failed: false,
+        };
+        let mut reader = MarkableReader::new(inner);
+        reader.mark();
+        let mut buf = vec![0; 4];
+        assert_eq!(
+            std::io::ErrorKind::BrokenPipe,
+            reader.read(&mut buf).unwrap_err().kind(),
+            "the error should be reported when no bytes were read"
+        );
+        assert_eq!(
+            0,
+            reader.position(),
+            "the failed read should consume nothing"
+        );
+
+        reader.reset();
+        let mut rest = vec![0; input_data.len()];
+        reader
+            .read_exact(&mut rest)
+            .expect("should be able to read once the inner reader recovers");
+        assert_eq!(input_data, rest, "no bytes should be lost");
+    }
+
+    #[test]
+    fn test_limit_error_loses_no_bytes() {
+        let input_data: Vec<u8> = (0..8).collect();
+        let mut reader =
+            MarkableReader::new_with_limited_back_buffer(Cursor::new(input_data.clone()), 2);
+        reader.mark();
+        let mut buf = vec![0; 4];
+        assert_eq!(
+            std::io::ErrorKind::OutOfMemory,
+            reader.read_exact(&mut buf).unwrap_err().kind(),
+            "read should exceed the limit"
+        );
+
+        reader.clear_buffer();
+        let mut rest = vec![0; input_data.len()];
+        reader
+            .read_exact(&mut rest)
+            .expect("should be able to read the whole stream");
+        assert_eq!(input_data, rest, "the failed read should consume nothing");
+    }
+
+    #[test]
+    fn test_read_at() {
+        let input_data: Vec<u8> = (0..32).collect();
+        let mut reader = MarkableReader::new(Cursor::new(input_data.clone()));
+        let mut first = vec![0; 2];
+        reader
+            .read_exact(&mut first)
+            .expect("should be able to read 2 bytes");
+
+        let mut buf = vec![0; 4];
+        for offset in [0, 5, 20] {
+            assert_eq!(
+                4,
+                reader
+                    .read_at(offset, &mut buf)
+                    .expect("should be able to read ahead"),
+                "should fill the slice"
+            );
+            assert_eq!(
+                input_data[2 + offset..6 + offset],
+                buf,
+                "should copy from {offset} bytes ahead"
+            );
+        }
+        assert_eq!(2, reader.position(), "the cursor should not move");
+
+        let mut next = vec![0; 2];
+        reader
+            .read_exact(&mut next)
+            .expect("should be able to read after read_at");
+        assert_eq!(
+            input_data[2..4],
+            next,
+            "reads should continue at the cursor"
+        );
+    }
+
+    #[test]
+    fn test_read_at_past_eof() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![1, 2, 3, 4]));
+        let mut buf = vec![0; 4];
+        assert_eq!(
+            2,
+            reader
+                .read_at(2, &mut buf)
+                .expect("should be able to read up to the end"),
+            "should clamp at the end of the stream"
+        );
+        assert_eq!([3, 4], buf[..2], "should copy the last bytes");
+        assert_eq!(
+            0,
+            reader
+                .read_at(8, &mut buf)
+                .expect("should be able to read past the end"),
+            "nothing should be copied past the end"
+        );
+    }
+
+    #[test]
+    fn test_sticky_mark() {
+        let input_data: Vec<u8> = (0..16).collect();
+        let mut plain = MarkableReader::new(Cursor::new(input_data.clone()));
+        let mut sticky = MarkableReader::new(Cursor::new(input_data.clone()));
+        sticky.set_sticky_mark(true);
+
+        for reader in [&mut plain, &mut sticky] {
+            reader.mark();
+            let mut first_pass = vec![0; 4];
+            reader
+                .read_exact(&mut first_pass)
+                .expect("should be able to read the first pass");
+            reader.reset();
+            let mut second_pass = vec![0; 6];
+            reader
+                .read_exact(&mut second_pass)
+                .expect("should be able to read the second pass");
+            assert_eq!(
+                input_data[..6],
+                second_pass,
+                "reset should replay the first pass"
+            );
+            reader.reset();
+        }
+
+        assert!(!plain.is_marked(), "a plain reset should unmark");
+        assert_eq!(
+            6,
+            plain.position(),
+            "a second plain reset should have no effect"
+        );
+
+        assert!(sticky.is_marked(), "a sticky reset should stay marked");
+        assert_eq!(
+            0,
+            sticky.position(),
+            "a sticky reset should return to the mark"
+        );
+        assert_eq!(6, sticky.buffered_len(), "both passes should be retained");
+        let mut third_pass = vec![0; 8];
+        sticky
+            .read_exact(&mut third_pass)
+            .expect("should be able to read the third pass");
+        assert_eq!(
+            input_data[..8],
+            third_pass,
+            "should replay both passes again"
+        );
+        assert_eq!(8, sticky.buffered_len(), "new bytes should be retained");
+
+        sticky.clear_buffer();
+        assert!(!sticky.is_marked(), "clear_buffer should release the mark");
+        assert_eq!(0, sticky.buffered_len(), "nothing should be retained");
+    }
+
+    #[test]
+    fn test_builder_sticky_mark() {
+        let mut reader = MarkableReaderBuilder::new()
+            .sticky_mark(true)
+            .build(Cursor::new(vec![1, 2, 3]));
+        reader.mark();
+        let mut buf = vec![0; 2];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 2 bytes");
+        reader.reset();
+        assert!(
+            reader.is_marked(),
+            "the built reader should have sticky marks"
+        );
+    }
+
+    #[test]
+    fn test_into_buffered() {
+        let input_data: Vec<u8> = (0..64).collect();
+        let mut reader =
+            MarkableReader::new_with_limited_back_buffer(Cursor::new(input_data.clone()), 32);
+        let mut buf = vec![0; 4];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 4 bytes");
+        reader.mark();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 4 marked bytes");
+        reader.peek(4).expect("should be able to peek 4 bytes");
+
+        let mut buffered = reader.into_buffered();
+        assert!(buffered.is_marked(), "the mark should carry over");
+        assert_eq!(8, buffered.position(), "the position should carry over");
+        assert_eq!(
+            4,
+            buffered.buffered_len(),
+            "the marked bytes should carry over"
+        );
+        assert_eq!(
+            Some(32),
+            buffered.mark_limit(),
+            "the limit should carry over"
+        );
+
+        let mut marked = vec![0; 12];
+        buffered
+            .read_exact(&mut marked)
+            .expect("should be able to read on the buffered reader");
+        assert_eq!(
+            input_data[8..20],
+            marked,
+            "should continue where it left off"
+        );
+        buffered.reset();
+        let mut replayed = vec![0; 16];
+        buffered
+            .read_exact(&mut replayed)
+            .expect("should be able to read after reset");
+        assert_eq!(
+            input_data[4..20],
+            replayed,
+            "reset should replay from the mark"
+        );
+
+        let mut rest = Vec::new();
+        buffered
+            .read_to_end(&mut rest)
+            .expect("should be able to read the rest");
+        assert_eq!(input_data[20..], rest, "no bytes should be lost");
+    }
+
+    #[test]
+    fn test_on_consume() {
+        let input_data: Vec<u8> = (0..=255).cycle().take(10_000).collect();
+        let consumed = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut reader = MarkableReader::new(Cursor::new(input_data.clone()));
+        let tap = consumed.clone();
+        reader.on_consume(move |bytes| tap.lock().unwrap().extend_from_slice(bytes));
+
+        let mut buf = vec![0; 100];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 100 bytes");
+        reader.mark();
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read 100 marked bytes");
+        reader.reset();
+        let mut overlapping = vec![0; 150];
+        reader
+            .read_exact(&mut overlapping)
+            .expect("should be able to read past the replayed bytes");
+        reader.peek(10).expect("should be able to peek 10 bytes");
+        assert_eq!(
+            input_data[..250],
+            consumed.lock().unwrap()[..],
+            "replayed and peeked bytes should not be passed again"
+        );
+
+        reader.skip(50).expect("should be able to skip 50 bytes");
+        let mut rest = vec![0; input_data.len() - 300];
+        reader
+            .read_exact(&mut rest)
+            .expect("should be able to read the rest");
+        assert_eq!(
+            input_data,
+            *consumed.lock().unwrap(),
+            "the callback should see each byte of the stream once"
+        );
+    }
+
+    #[test]
+    fn test_reset_and_reclaim() {
+        let input_data: Vec<u8> = (0..=255).cycle().take(64 * 1024).collect();
+        let mut reader = MarkableReader::new(Cursor::new(input_data.clone()));
+        let initial_capacity = reader.mark_capacity();
+        reader.mark();
+        let mut buf = vec![0; input_data.len()];
+        reader
+            .read_exact(&mut buf)
+            .expect("should be able to read all bytes");
+        reader.reset_and_reclaim();
+        let grown_capacity = reader.mark_capacity();
+        assert!(
+            grown_capacity >= input_data.len(),
+            "the mark buffer should have grown"
+        );
+
+        let mut partial = vec![0; 1024];
+        reader
+            .read_exact(&mut partial)
+            .expect("should be able to replay 1024 bytes");
+        assert_eq!(
+            grown_capacity,
+            reader.mark_capacity(),
+            "the replay should be kept until it is read"
+        );
+
+        let mut rest = vec![0; input_data.len() - partial.len()];
+        reader
+            .read_exact(&mut rest)
+            .expect("should be able to replay the rest");
+        assert_eq!(input_data[1024..], rest, "the replay should be intact");
+        let mut next = [0; 1];
+        assert!(reader.read(&mut next).is_err(), "the stream should be done");
+        assert_eq!(
+            initial_capacity,
+            reader.mark_capacity(),
+            "capacity should drop back once the replay is read"
+        );
+    }
+
+    #[test]
+    fn test_available_and_consume() {
+        let input_data: Vec<u8> = (0..64).collect();
+        let mut reader = MarkableReader::new(Cursor::new(input_data.clone()));
+        let mut consumed = Vec::new();
+
+        reader.mark();
+        while consumed.len() < 10 {
+            let available = reader.available().expect("should be able to fill");
+            let amt = available.len().min(10 - consumed.len());
+            consumed.extend_from_slice(&available[..amt]);
+            reader.consume(amt);
+        }
+        assert_eq!(input_data[..10], consumed, "should see the stream in order");
+        assert_eq!(10, reader.position(), "consume should advance the position");
+
+        reader.reset();
+        let replay = reader
+            .available()
+            .expect("should be able to fill after reset");
+        assert_eq!(
+            input_data[..replay.len()],
+            replay[..],
+            "should start with the replayed bytes"
+        );
+        let mut rest = Vec::new();
+        loop {
+            let available = reader.available().expect("should be able to fill");
+            if available.is_empty() {
+                break;
+            }
+            let amt = available.len();
+            rest.extend_from_slice(available);
+            reader.consume(amt);
+        }
+        assert_eq!(input_data, rest, "the replay and the rest should follow");
+    }
+
+    #[test]
+    fn test_available_at_mark_limit() {
+        let mut reader = MarkableReader::new_with_limited_back_buffer(Cursor::new(vec![5; 16]), 4);
+        reader.mark();
+        let available = reader.available().expect("should be able to fill");
+        assert!(available.len() <= 4, "should be cut short at the limit");
+        while reader.remaining_limit() != Some(0) {
+            let amt = reader.available().expect("should be able to fill").len();
+            reader.consume(amt);
+        }
+        assert_eq!(
+            std::io::ErrorKind::OutOfMemory,
+            reader.available().unwrap_err().kind(),
+            "should fail once the limit is reached"
+        );
+
+        reader.reset();
+        assert_eq!(
+            &[5, 5, 5, 5],
+            reader.available().expect("should be able to replay"),
+            "should replay the marked bytes"
+        );
+    }
+
+    #[test]
+    fn test_reset_tagged() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![1, 2, 3, 4]));
+        let token = reader.mark_tagged();
+        let mut buf = [0; 2];
+        reader.read_exact(&mut buf).expect("should be able to read");
+        reader.reset_tagged(token).expect("token should be current");
+        reader.read_exact(&mut buf).expect("should be able to read");
+        assert_eq!([1, 2], buf, "should replay from the mark");
+
+        let token = reader.mark_tagged();
+        reader.read_exact(&mut buf).expect("should be able to read");
+        reader.clear_buffer();
+        assert_eq!(
+            std::io::ErrorKind::InvalidInput,
+            reader.reset_tagged(token).unwrap_err().kind(),
+            "token should be stale after clear_buffer"
+        );
+
+        let stale = reader.mark_tagged();
+        let _current = reader.mark_tagged();
+        assert!(
+            reader.reset_tagged(stale).is_err(),
+            "token should be stale after marking again"
+        );
+        assert!(reader.is_marked(), "stale token should leave the mark");
+    }
+
+    #[test]
+    fn test_lookahead() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![1, 2, 3, 4, 5]));
+        let mut buf = [0; 1];
+        reader.read_exact(&mut buf).expect("should be able to read");
+        assert_eq!(&[2, 3, 4], reader.lookahead(3).expect("should look ahead"));
+        assert_eq!(
+            &[2],
+            reader.lookahead(1).expect("should look ahead"),
+            "should be a prefix of the longer lookahead"
+        );
+        assert_eq!(
+            &[2, 3, 4, 5],
+            reader.lookahead(8).expect("should look ahead"),
+            "should stop at the end of the stream"
+        );
+
+        let mut buf = [0; 4];
+        reader.read_exact(&mut buf).expect("should be able to read");
+        assert_eq!([2, 3, 4, 5], buf, "lookahead should consume nothing");
+    }
+
+    #[test]
+    fn test_sticky_mark_with_transaction_and_guard() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![1, 2, 3, 4]));
+        reader.set_sticky_mark(true);
+        let result: Result<(), &str> = reader.transaction(|reader| {
+            let mut buf = [0; 2];
+            reader.read_exact(&mut buf).expect("should be able to read");
+            Err("fail")
+        });
+        assert!(result.is_err(), "transaction should fail");
+        assert!(!reader.is_marked(), "a failed transaction should unmark");
+        assert_eq!(0, reader.position(), "a failed transaction should rewind");
+
+        {
+            let mut guard = reader.mark_guard();
+            let mut buf = [0; 3];
+            guard.read_exact(&mut buf).expect("should be able to read");
+        }
+        assert!(!reader.is_marked(), "dropping the guard should unmark");
+        assert_eq!(0, reader.position(), "dropping the guard should rewind");
+
+        let mut buf = [0; 4];
+        reader.read_exact(&mut buf).expect("should be able to read");
+        assert_eq!([1, 2, 3, 4], buf, "should read the whole stream");
+        assert_eq!(0, reader.buffered_len(), "nothing should be retained");
+    }
+
+    #[test]
+    fn test_available_and_consume_with_sliding_window() {
+        let input_data: Vec<u8> = (0..50).collect();
+        let mut reader =
+            MarkableReader::new_with_limited_back_buffer(Cursor::new(input_data.clone()), 4);
+        reader.set_sliding_window(true);
+        reader.mark();
+        let mut consumed = Vec::new();
+        loop {
+            let available = reader.available().expect("should be able to fill");
+            if available.is_empty() {
+                break;
+            }
+            let amt = available.len();
+            consumed.extend_from_slice(available);
+            reader.consume(amt);
+        }
+        assert_eq!(input_data, consumed, "should read the whole stream");
+        assert_eq!(4, reader.buffered_len(), "should keep only the window");
+    }
+
+    #[test]
+    fn test_consume_unmarked_taps_skipped_bytes() {
+        let input_data: Vec<u8> = (0..20).collect();
+        let tapped = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut reader =
+            MarkableReader::new_with_limited_back_buffer(Cursor::new(input_data.clone()), 8);
+        let tap = tapped.clone();
+        reader.on_consume(move |bytes| tap.lock().unwrap().extend_from_slice(bytes));
+        reader.peek(12).expect("should be able to peek");
+        let buffered = reader.buffered_bytes().len();
+        reader.consume(5);
+        assert_eq!(5, reader.position(), "consume should advance the position");
+        assert_eq!(
+            buffered - 5,
+            reader.buffered_bytes().len(),
+            "the rest should stay buffered"
+        );
+        assert_eq!(
+            input_data[..5],
+            tapped.lock().unwrap()[..],
+            "should tap the skipped bytes"
+        );
+    }
+
+    #[test]
+    fn test_peeks_reuse_scratch_buffer() {
+        let input_data: Vec<u8> = (0..=255).cycle().take(1024).collect();
+        let mut reader = MarkableReader::new(Cursor::new(input_data.clone()));
+        let mut buf = vec![0; 64];
+        reader
+            .peek(64)
+            .expect("should be able to peek the first block");
+        let scratch_ptr = reader.scratch.as_ptr();
+        let scratch_capacity = reader.scratch.capacity();
+
+        let mut read_data = Vec::new();
+        for _ in 0..16 {
+            reader.peek(64).expect("should be able to peek a block");
+            reader
+                .read_exact(&mut buf)
+                .expect("should be able to read a block");
+            read_data.extend_from_slice(&buf);
+            assert_eq!(
+                scratch_ptr,
+                reader.scratch.as_ptr(),
+                "scratch should be reused"
+            );
+            assert_eq!(
+                scratch_capacity,
+                reader.scratch.capacity(),
+                "scratch should not grow"
+            );
+        }
+
+        assert_eq!(input_data, read_data, "reads should match the input data");
+    }
+
+    #[test]
+    fn test_peek_past_end_of_short_stream() {
+        let mut reader = MarkableReader::new(Cursor::new((0..10).collect::<Vec<u8>>()));
+        let peeked = reader.peek(1 << 34).expect("should be able to peek");
+        assert_eq!(10, peeked.len(), "should peek up to the end of the stream");
+        assert!(
+            reader.scratch.capacity() <= DEFAULT_BUFFER_SIZE,
+            "scratch should not be sized from the request"
+        );
+    }
+
+    #[test]
+    fn test_find_with_unbounded_scan() {
+        let mut reader = MarkableReader::new(Cursor::new(b"key=value".to_vec()));
+        assert_eq!(
+            Some(3),
+            reader
+                .find(b"=", usize::MAX)
+                .expect("should be able to scan"),
+            "should find the needle in a short stream"
+        );
+        assert_eq!(
+            None,
+            reader
+                .find(b"x", usize::MAX)
+                .expect("should be able to scan"),
+            "should scan to the end of the stream"
+        );
+    }
+
+    #[test]
+    fn test_read_at_past_end() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![1, 2, 3, 4]));
+        let mut buf = [0; 2];
+        assert_eq!(
+            0,
+            reader
+                .read_at(usize::MAX, &mut buf)
+                .expect("should be able to peek"),
+            "an offset past the end should copy nothing"
+        );
+        assert_eq!(
+            0,
+            reader
+                .read_at(1 << 40, &mut buf)
+                .expect("should be able to peek"),
+            "a large offset should copy nothing"
+        );
+        assert_eq!(
+            1,
+            reader.read_at(3, &mut buf).expect("should be able to peek"),
+            "should copy up to the end of the stream"
+        );
+        assert_eq!(4, buf[0], "should copy the last byte");
+    }
+
+    #[test]
+    fn test_seek_with_prefix() {
+        let input_data: Vec<u8> = (0..10).collect();
+        let mut inner = Cursor::new(input_data.clone());
+        inner.set_position(3);
+        let mut reader = MarkableReader::with_prefix(inner, input_data[..3].to_vec());
+        assert_eq!(
+            0,
+            reader.stream_position().expect("should be able to seek"),
+            "the prefix should count as read from the inner reader"
+        );
+        assert_eq!(
+            1,
+            reader
+                .seek(SeekFrom::Current(1))
+                .expect("should be able to seek"),
+            "should seek forward from the start of the prefix"
+        );
+        let mut buf = [0; 2];
+        reader.read_exact(&mut buf).expect("should be able to read");
+        assert_eq!([1, 2], buf, "should read on from the seek");
+
+        let mut reader = MarkableReader::with_prefix(Cursor::new(input_data.clone()), vec![7; 3]);
+        assert_eq!(
+            std::io::ErrorKind::InvalidInput,
+            reader.stream_position().unwrap_err().kind(),
+            "a prefix the inner reader has not read should not be seekable"
+        );
+        assert_eq!(
+            std::io::ErrorKind::InvalidInput,
+            reader.seek(SeekFrom::Current(1)).unwrap_err().kind(),
+            "a prefix the inner reader has not read should not be seekable"
+        );
+        let mut buf = [0; 4];
+        reader.read_exact(&mut buf).expect("should be able to read");
+        assert_eq!([7, 7, 7, 0], buf, "a failed seek should leave the stream");
+    }
+
+    #[test]
+    fn test_seek_after_unread() {
+        let input_data: Vec<u8> = (0..10).collect();
+        let mut reader = MarkableReader::new(Cursor::new(input_data.clone()));
+        let mut buf = [0; 4];
+        reader.read_exact(&mut buf).expect("should be able to read");
+        reader.unread(&buf[2..]).expect("should be able to unread");
+        assert_eq!(
+            2,
+            reader.stream_position().expect("should be able to seek"),
+            "unread bytes should count as not yet read"
+        );
+
+        let mut reader = MarkableReader::new(Cursor::new(input_data.clone()));
+        reader.unread(&[9, 9]).expect("should be able to unread");
+        assert_eq!(
+            std::io::ErrorKind::InvalidInput,
+            reader.seek(SeekFrom::Current(-1)).unwrap_err().kind(),
+            "should fail rather than seek before the inner stream"
+        );
+        let mut buf = [0; 3];
+        reader.read_exact(&mut buf).expect("should be able to read");
+        assert_eq!([9, 9, 0], buf, "a failed seek should leave the stream");
     }
 }
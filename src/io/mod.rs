@@ -1,17 +1,282 @@
 mod buffer;
 mod buffered_markable_reader;
+mod byte_read_ext;
+mod framed_reader;
+mod line_reader;
+mod markable_buf_reader;
 mod markable_reader;
+mod varint_read_ext;
 
-pub use buffered_markable_reader::BufferedMarkableReader;
-pub use markable_reader::MarkableReader;
+pub use buffered_markable_reader::{
+    BufferedMarkGuard, BufferedMarkableBytes, BufferedMarkableIntoBytes, BufferedMarkableReader,
+    BufferedRecords, FileMarkableReader,
+};
+pub use byte_read_ext::ByteReadExt;
+pub use framed_reader::FramedReader;
+pub use line_reader::LineReader;
+pub use markable_buf_reader::MarkableBufReader;
+pub use markable_reader::{
+    MarkGuard, MarkableBytes, MarkableChain, MarkableIntoBytes, MarkableReader,
+    MarkableReaderBuilder, MarkableTake, Records,
+};
+pub use varint_read_ext::VarintReadExt;
 
 const DEFAULT_BUFFER_SIZE: usize = 8 * 1024;
 const DEFAULT_MARKER_BUFFER_SIZE: usize = 2 * 1024;
+const DEFAULT_MARKER_BUFFER_LIMIT: usize = 1024 * 1024;
+
+/// Identifies a nested mark created by `push_mark`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MarkId(usize);
+
+/// Identifies a mark created by `mark_tagged`, so `reset_tagged` can detect stale marks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MarkToken(u64);
+
+/// A byte-order mark found at the start of a text stream by `skip_bom`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Bom {
+    /// The UTF-8 byte-order mark, `EF BB BF`
+    Utf8,
+    /// The little endian UTF-16 byte-order mark, `FF FE`
+    Utf16Le,
+    /// The big endian UTF-16 byte-order mark, `FE FF`
+    Utf16Be,
+}
+
+impl Bom {
+    /// Returns the bytes of this byte-order mark
+    pub fn as_bytes(&self) -> &'static [u8] {
+        match self {
+            Bom::Utf8 => &[0xEF, 0xBB, 0xBF],
+            Bom::Utf16Le => &[0xFF, 0xFE],
+            Bom::Utf16Be => &[0xFE, 0xFF],
+        }
+    }
+
+    /// Finds the byte-order mark that `bytes` starts with, if any
+    fn detect(bytes: &[u8]) -> Option<Bom> {
+        [Bom::Utf8, Bom::Utf16Le, Bom::Utf16Be]
+            .into_iter()
+            .find(|bom| bytes.starts_with(bom.as_bytes()))
+    }
+}
+
+/// An error raised by the mark buffer of a reader, as returned by `try_read_marked`
+#[derive(Debug)]
+pub enum MarkBufferError {
+    /// Holding the bytes of a read would exceed the limit of the mark buffer
+    LimitExceeded {
+        /// The number of bytes the mark buffer would have needed to hold
+        used: usize,
+        /// The limit of the mark buffer
+        limit: usize,
+    },
+    /// The inner reader failed
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for MarkBufferError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MarkBufferError::LimitExceeded { used, limit } => write!(
+                f,
+                "mark buffer limit exceeded: {used} bytes needed but the limit is {limit}"
+            ),
+            MarkBufferError::Io(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for MarkBufferError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            MarkBufferError::LimitExceeded { .. } => None,
+            MarkBufferError::Io(err) => Some(err),
+        }
+    }
+}
+
+/// The limit is reported as `ErrorKind::OutOfMemory`, keeping the typed error as the
+/// payload so it can be recovered
+impl From<MarkBufferError> for std::io::Error {
+    fn from(err: MarkBufferError) -> Self {
+        match err {
+            MarkBufferError::LimitExceeded { .. } => {
+                std::io::Error::new(std::io::ErrorKind::OutOfMemory, err)
+            }
+            MarkBufferError::Io(err) => err,
+        }
+    }
+}
+
+/// Recovers a `MarkBufferError` carried by an `std::io::Error`, wrapping any other
+/// error as `MarkBufferError::Io`
+impl From<std::io::Error> for MarkBufferError {
+    fn from(err: std::io::Error) -> Self {
+        if !err
+            .get_ref()
+            .is_some_and(|inner| inner.is::<MarkBufferError>())
+        {
+            return MarkBufferError::Io(err);
+        }
+
+        match err
+            .into_inner()
+            .map(|inner| inner.downcast::<MarkBufferError>())
+        {
+            Some(Ok(err)) => *err,
+            _ => unreachable!("the payload was checked to be a MarkBufferError"),
+        }
+    }
+}
+
+mod sealed {
+    pub trait Sealed {}
+
+    impl<T> Sealed for T where T: std::io::Read + std::io::Seek + ?Sized {}
+}
+
+/// Marks inner readers that can be seeked, for which both readers implement
+/// `std::io::Seek`. This trait is sealed and implemented for every `Read + Seek` type.
+pub trait SeekableInner: std::io::Read + std::io::Seek + sealed::Sealed {}
+
+impl<T> SeekableInner for T where T: std::io::Read + std::io::Seek + ?Sized {}
+
+/// A callback registered with `on_limit`
+type LimitHook = std::sync::Arc<std::sync::Mutex<dyn FnMut(usize, usize) -> Option<usize> + Send>>;
+
+/// A callback registered with `on_consume`
+type ConsumeHook = std::sync::Arc<std::sync::Mutex<dyn FnMut(&[u8]) + Send>>;
+
+/// The outcome of a failed `checked_read`, separating the end of the stream and the
+/// mark buffer limit from other errors
+#[derive(Debug)]
+pub enum ReadError {
+    /// The end of the stream was reached before any bytes were read
+    Eof,
+    /// Holding the bytes of the read would exceed the limit of the mark buffer
+    LimitExceeded {
+        /// The number of bytes the mark buffer would have needed to hold
+        used: usize,
+        /// The limit of the mark buffer
+        limit: usize,
+    },
+    /// The inner reader failed
+    Io(std::io::Error),
+}
+
+impl std::fmt::Display for ReadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ReadError::Eof => write!(f, "end of stream"),
+            ReadError::LimitExceeded { used, limit } => MarkBufferError::LimitExceeded {
+                used: *used,
+                limit: *limit,
+            }
+            .fmt(f),
+            ReadError::Io(err) => err.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for ReadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ReadError::Io(err) => Some(err),
+            _ => None,
+        }
+    }
+}
+
+impl From<MarkBufferError> for ReadError {
+    fn from(err: MarkBufferError) -> Self {
+        match err {
+            MarkBufferError::LimitExceeded { used, limit } => {
+                ReadError::LimitExceeded { used, limit }
+            }
+            MarkBufferError::Io(err) => ReadError::Io(err),
+        }
+    }
+}
+
+/// The error for a `SeekFrom::Current` seek that lands before the start of the inner
+/// stream, which happens when buffered bytes did not come from the inner reader
+fn seek_before_inner_start() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidInput,
+        "seek position is before the start of the inner stream",
+    )
+}
+
+/// Returns the longest read of at most `length` bytes that takes an inner reader at
+/// `inner_offset` to a multiple of `align`, which is 0 if `length` falls short of the
+/// next one
+fn aligned_len(inner_offset: u64, align: usize, length: usize) -> usize {
+    let misalignment = (inner_offset % align as u64) as usize;
+    (misalignment.saturating_add(length) / align * align).saturating_sub(misalignment)
+}
+
+/// Builds an `on_limit` callback that doubles the limit until it covers the bytes
+/// needed, without going past `max`
+fn doubling_limit(max: usize) -> impl FnMut(usize, usize) -> Option<usize> + Send + 'static {
+    move |used, limit| {
+        if limit >= max {
+            return None;
+        }
+
+        let mut grown = limit.max(1);
+        while grown < used && grown < max {
+            grown = grown.saturating_mul(2);
+        }
+        Some(grown.min(max))
+    }
+}
+
+/// A copy of the state of a reader, taken by `snapshot` and rolled back to by `restore`.
+/// Any number of snapshots can be held at once.
+///
+/// The inner reader is not part of the snapshot: it has to be repositioned on its own,
+/// for a seekable source to `inner_offset`, before the snapshot is restored.
+#[derive(Clone, Debug)]
+pub struct ReaderSnapshot {
+    is_marked: bool,
+    mark_invalidated: bool,
+    inner_complete: bool,
+    mark_buffer: buffer::Buffer,
+    lookahead: buffer::Buffer,
+    position: u64,
+    inner_offset: u64,
+    nested_marks: Vec<NestedMark>,
+    named_marks: std::collections::HashMap<String, usize>,
+}
+
+impl ReaderSnapshot {
+    /// Gets the position of the reader when the snapshot was taken
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    /// Gets the number of bytes that had been read from the inner reader when the
+    /// snapshot was taken, which is where the inner reader needs to be for `restore`
+    pub fn inner_offset(&self) -> u64 {
+        self.inner_offset
+    }
+}
+
+/// A nested mark within the mark buffer
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct NestedMark {
+    /// Offset of the mark within the mark buffer
+    offset: usize,
+    /// Whether the stream was unmarked before this mark was pushed
+    starts_marking: bool,
+}
 
 pub trait MarkerStream {
-    // Marks the location of the inner stream. From tis point forward
+    /// Marks the location of the inner stream. From this point forward
     /// reads will be cached. If the stream was marked prior to this call
-    /// the current buffer will be discarded.
+    /// the bytes read since that mark will be discarded.
     ///
     /// Returns the number of bytes that were discarded as a result of this operation
     fn mark(&mut self) -> usize;
@@ -22,4 +287,13 @@ pub trait MarkerStream {
 
     /// Clears the current buffer dropping any values that have been cached.
     fn clear_buffer(&mut self);
+
+    /// Returns whether the stream is currently marked.
+    /// Defaults to `false` for implementors that do not track this.
+    fn is_marked(&self) -> bool {
+        false
+    }
 }
+
+/// A boxed `MarkerStream`, for holding markable readers of different types together
+pub type BoxedMarkerStream = Box<dyn MarkerStream + 'static>;
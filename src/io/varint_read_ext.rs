@@ -0,0 +1,193 @@
+use std::io::Read;
+
+use super::{BufferedMarkableReader, MarkableReader};
+
+/// The most bytes a 64 bit LEB128 value can be encoded in
+const MAX_VARINT_LENGTH: usize = 10;
+
+/// Decodes LEB128 variable-length integers, as used by Protocol Buffers and WASM.
+/// If a value is malformed or the stream ends partway through it, an error is
+/// returned and the stream is reset to the start of the value.
+pub trait VarintReadExt {
+    /// Reads an unsigned LEB128 value. Returns an `ErrorKind::InvalidData` error if
+    /// the value does not fit in a `u64`.
+    fn read_varint_u64(&mut self) -> std::io::Result<u64>;
+
+    /// Reads a signed LEB128 value. Returns an `ErrorKind::InvalidData` error if the
+    /// value does not fit in an `i64`.
+    fn read_varint_i64(&mut self) -> std::io::Result<i64>;
+}
+
+impl<R> VarintReadExt for MarkableReader<R>
+where
+    R: Read,
+{
+    fn read_varint_u64(&mut self) -> std::io::Result<u64> {
+        self.transaction(decode_varint_u64)
+    }
+
+    fn read_varint_i64(&mut self) -> std::io::Result<i64> {
+        self.transaction(decode_varint_i64)
+    }
+}
+
+impl<R> VarintReadExt for BufferedMarkableReader<R>
+where
+    R: Read,
+{
+    fn read_varint_u64(&mut self) -> std::io::Result<u64> {
+        self.transaction(decode_varint_u64)
+    }
+
+    fn read_varint_i64(&mut self) -> std::io::Result<i64> {
+        self.transaction(decode_varint_i64)
+    }
+}
+
+fn decode_varint_u64<R>(reader: &mut R) -> std::io::Result<u64>
+where
+    R: Read + ?Sized,
+{
+    let mut value = 0;
+    let mut byte = [0; 1];
+    for index in 0..MAX_VARINT_LENGTH {
+        reader.read_exact(&mut byte)?;
+        if index == MAX_VARINT_LENGTH - 1 && byte[0] > 1 {
+            // only the lowest bit of a tenth byte is within 64 bits
+            return Err(varint_overflow());
+        }
+
+        value |= u64::from(byte[0] & 0x7F) << (index * 7);
+        if byte[0] & 0x80 == 0 {
+            return Ok(value);
+        }
+    }
+
+    Err(varint_overflow())
+}
+
+fn decode_varint_i64<R>(reader: &mut R) -> std::io::Result<i64>
+where
+    R: Read + ?Sized,
+{
+    let mut value = 0;
+    let mut byte = [0; 1];
+    for index in 0..MAX_VARINT_LENGTH {
+        reader.read_exact(&mut byte)?;
+        let shift = index * 7;
+        if index == MAX_VARINT_LENGTH - 1 && byte[0] != 0x00 && byte[0] != 0x7F {
+            // a tenth byte may only carry the sign bit
+            return Err(varint_overflow());
+        }
+
+        value |= i64::from(byte[0] & 0x7F) << shift;
+        if byte[0] & 0x80 == 0 {
+            let width = shift + 7;
+            if width < 64 && byte[0] & 0x40 != 0 {
+                // sign extend from the last bit read
+                value |= -1 << width;
+            }
+
+            return Ok(value);
+        }
+    }
+
+    Err(varint_overflow())
+}
+
+fn varint_overflow() -> std::io::Error {
+    std::io::Error::new(
+        std::io::ErrorKind::InvalidData,
+        "varint does not fit in 64 bits",
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use crate::{BufferedMarkableReader, MarkableReader};
+
+    use super::VarintReadExt;
+
+    #[test]
+    fn test_single_byte_varint() {
+        let mut reader = MarkableReader::new(Cursor::new(vec![0x05, 0x7F]));
+        assert_eq!(
+            5,
+            reader
+                .read_varint_u64()
+                .expect("should be able to read a varint")
+        );
+        assert_eq!(
+            -1,
+            reader
+                .read_varint_i64()
+                .expect("should be able to read a varint")
+        );
+    }
+
+    #[test]
+    fn test_multi_byte_varint() {
+        let mut reader = BufferedMarkableReader::new(Cursor::new(vec![
+            0xE5, 0x8E, 0x26, 0xC0, 0xBB, 0x78, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF,
+            0xFF, 0x01,
+        ]));
+        assert_eq!(
+            624485,
+            reader
+                .read_varint_u64()
+                .expect("should be able to read a varint")
+        );
+        assert_eq!(
+            -123456,
+            reader
+                .read_varint_i64()
+                .expect("should be able to read a varint")
+        );
+        assert_eq!(
+            u64::MAX,
+            reader
+                .read_varint_u64()
+                .expect("should be able to read a 10 byte varint")
+        );
+    }
+
+    #[test]
+    fn test_overflowing_varint_is_rolled_back() {
+        let input_data = vec![0xFF; 11];
+        let mut reader = MarkableReader::new(Cursor::new(input_data.clone()));
+        assert_eq!(
+            std::io::ErrorKind::InvalidData,
+            reader.read_varint_u64().unwrap_err().kind(),
+            "varint longer than 10 bytes should be rejected"
+        );
+        assert_eq!(
+            std::io::ErrorKind::InvalidData,
+            reader.read_varint_i64().unwrap_err().kind(),
+            "varint longer than 10 bytes should be rejected"
+        );
+
+        let mut buf = vec![0; input_data.len()];
+        reader
+            .read_exact(&mut buf)
+            .expect("malformed varint should not be consumed");
+        assert_eq!(input_data, buf, "stream should be unchanged");
+    }
+
+    #[test]
+    fn test_truncated_varint_is_rolled_back() {
+        let mut reader = BufferedMarkableReader::new(Cursor::new(vec![0x80, 0x80]));
+        assert_eq!(
+            std::io::ErrorKind::UnexpectedEof,
+            reader.read_varint_u64().unwrap_err().kind(),
+            "truncated varint should not be read"
+        );
+
+        let mut buf = vec![0; 2];
+        reader
+            .read_exact(&mut buf)
+            .expect("truncated varint should not be consumed");
+        assert_eq!(vec![0x80, 0x80], buf, "stream should be unchanged");
+    }
+}
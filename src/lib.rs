@@ -1,5 +1,31 @@
 mod io;
+pub mod prelude;
 
+pub use io::Bom;
+pub use io::BoxedMarkerStream;
+pub use io::BufferedMarkGuard;
+pub use io::BufferedMarkableBytes;
+pub use io::BufferedMarkableIntoBytes;
 pub use io::BufferedMarkableReader;
+pub use io::BufferedRecords;
+pub use io::ByteReadExt;
+pub use io::FileMarkableReader;
+pub use io::FramedReader;
+pub use io::LineReader;
+pub use io::MarkBufferError;
+pub use io::MarkGuard;
+pub use io::MarkId;
+pub use io::MarkToken;
+pub use io::MarkableBufReader;
+pub use io::MarkableBytes;
+pub use io::MarkableChain;
+pub use io::MarkableIntoBytes;
 pub use io::MarkableReader;
-pub use io::MarkerStream;
\ No newline at end of file
+pub use io::MarkableReaderBuilder;
+pub use io::MarkableTake;
+pub use io::MarkerStream;
+pub use io::ReadError;
+pub use io::ReaderSnapshot;
+pub use io::Records;
+pub use io::SeekableInner;
+pub use io::VarintReadExt;
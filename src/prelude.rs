@@ -0,0 +1,24 @@
+//! Re-exports the readers and the traits needed to use them, so that a single
+//! glob import brings everything into scope.
+//!
+//! ```
+//! use std::io::{Cursor, Read};
+//!
+//! use markable_reader::prelude::*;
+//!
+//! let mut reader = MarkableReader::new(Cursor::new(vec![0x01, 0xAC, 0x02]));
+//! reader.mark();
+//! assert_eq!(1, reader.read_u8().unwrap());
+//! reader.reset();
+//! assert_eq!(1, reader.read_u8().unwrap());
+//!
+//! let mut buffered = BufferedMarkableReader::new(Cursor::new(vec![0xAC, 0x02, 0x07]));
+//! assert_eq!(300, buffered.read_varint_u64().unwrap());
+//! let mut rest = [0; 1];
+//! buffered.read_exact(&mut rest).unwrap();
+//! assert_eq!([0x07], rest);
+//! ```
+
+pub use crate::io::{
+    BufferedMarkableReader, ByteReadExt, MarkableReader, MarkerStream, VarintReadExt,
+};